@@ -1,15 +1,41 @@
-use crate::{cli::CLI, controller::Controller, db::Database, entity::group};
+use crate::{
+    cli::CLI,
+    controller::{
+        Archived, Controller, DebtsChanged, InviteError, NotAMember, NotAnAdmin, NotInGroup,
+        RemovalError, MAX_INVITE_DAYS,
+    },
+    db::{Database, NewExpense},
+    entity::{
+        expense::{self, SplitMode},
+        group::{self, Simplification},
+        payment, user,
+        user_group::Role,
+    },
+    settlement::{self, SplitError},
+    storage::{DbStorage, Dialogue},
+};
 use async_once::AsyncOnce;
 use rust_decimal::Decimal;
-use std::{cmp::Ordering, collections::HashMap};
+use serde::{Deserialize, Serialize};
+use std::{ops::ControlFlow, sync::Arc};
 use teloxide::{
-    dispatching::dialogue::{self, InMemStorage},
+    dispatching::{DpHandlerDescription, UpdateHandler},
+    dptree::{
+        di::{DependencyMap, DependencySupplier},
+        HandlerDescription,
+    },
+    payloads::SendMessage,
     prelude::*,
+    requests::JsonRequest,
+    types::{
+        ForceReply, InlineKeyboardButton, InlineKeyboardMarkup, MediaKind, MediaText,
+        MessageCommon, MessageKind, User,
+    },
     utils::command::BotCommands,
 };
 use tracing::info;
 
-type MyDialogue = Dialogue<ChatState, InMemStorage<ChatState>>;
+type MyDialogue = Dialogue<ChatState>;
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
 #[derive(BotCommands, Clone, Debug)]
@@ -20,42 +46,232 @@ type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 enum Command {
     #[command(description = "display this text")]
     Help,
-    #[command(description = "create new group and put yourself as it's first member")]
-    CreateGroup,
-    #[command(description = "add member to a group")]
-    AddMemberToGroup,
-    #[command(description = "add an expense")]
-    AddExpense,
+    #[command(description = "off")]
+    Start(String),
+    #[command(
+        description = "create new group and put yourself as it's first member, e.g. `/creategroup Trip EUR`"
+    )]
+    CreateGroup(String),
+    #[command(description = "change the currency of a group")]
+    SetCurrency,
+    #[command(description = "choose how debts of a group are simplified")]
+    SetSimplification,
+    #[command(description = "give a group a new name")]
+    RenameGroup,
+    #[command(description = "archive a group you're done with or bring an archived one back")]
+    ArchiveGroup,
+    #[command(description = "delete a group along with its expenses and payments")]
+    DeleteGroup,
+    #[command(description = "set an exchange rate, for admins of the bot")]
+    SetRate,
+    #[command(description = "add member to a group, e.g. `/addmembertogroup @username`")]
+    AddMemberToGroup(String),
+    #[command(description = "create a link which adds whoever opens it to a group")]
+    Invite,
+    #[command(
+        description = "hand a member who isn't on Telegram over to you or to another member, admins only"
+    )]
+    MergeMember,
+    #[command(description = "let a member manage a group along with you")]
+    Promote,
+    #[command(description = "turn an admin of a group back into a regular member")]
+    Demote,
+    #[command(description = "leave a group once you're settled up")]
+    LeaveGroup,
+    #[command(description = "remove a member from a group, for admins of the group")]
+    RemoveMember,
+    #[command(description = "add an expense, e.g. `/addexpense 12.50 USD pizza`")]
+    AddExpense(String),
+    #[command(description = "edit an expense you've added, admins may edit any")]
+    EditExpense,
+    #[command(description = "delete an expense you've added, admins may delete any")]
+    DeleteExpense,
     #[command(description = "list all expenses in a group")]
     ListExpensesInGroup,
-    #[command(description = "list all your groups")]
-    ListMyGroups,
+    #[command(description = "list your groups, `/listmygroups all` includes the archived ones")]
+    ListMyGroups(String),
+    #[command(description = "record that you paid money back to a group member")]
+    SettleUp,
+    #[command(description = "mark all suggested transfers in a group as paid")]
+    SettleAll,
+    #[command(description = "delete a payment you've made or received, admins may delete any")]
+    DeletePayment,
     #[command(description = "cancel whatever you do")]
     Cancel,
 }
 
-#[derive(Clone, Default)]
+/// What has been given along with `/addexpense`
+#[derive(Clone, Serialize, Deserialize)]
+struct ExpenseDraft {
+    amount: Decimal,
+    currency: Option<String>,
+    note: Option<String>,
+}
+
+/// State of the dialogue of a user in a chat, kept in the database between messages, see `DbStorage`
+#[derive(Clone, Default, Serialize, Deserialize)]
 enum ChatState {
     #[default]
     Start,
     // ----- Add new expense
-    ReceiveGroupIdForExpense,
+    ReceiveGroupIdForExpense {
+        draft: Option<ExpenseDraft>,
+    },
     RecieveAmountSpent {
         group_id: i64,
     },
+    ReceiveExchangeRate {
+        group_id: i64,
+        amount: Decimal,
+        currency: String,
+        note: Option<String>,
+    },
     ReceiveNote {
         group_id: i64,
         amount: Decimal,
+        currency: String,
+        exchange_rate: Decimal,
+    },
+    ReceivePayer {
+        group_id: i64,
+        amount: Decimal,
+        currency: String,
+        exchange_rate: Decimal,
+        note: String,
+    },
+    ReceiveSplitMode {
+        group_id: i64,
+        amount: Decimal,
+        currency: String,
+        exchange_rate: Decimal,
+        note: String,
+        payers: Vec<(i64, Decimal)>,
+    },
+    ReceiveParticipants {
+        group_id: i64,
+        amount: Decimal,
+        currency: String,
+        exchange_rate: Decimal,
+        note: String,
+        payers: Vec<(i64, Decimal)>,
+        split_mode: SplitMode,
+    },
+    // ----- Edit expense
+    ReceiveGroupIdForEditExpense,
+    ReceiveExpenseIdForEdit {
+        group_id: i64,
+    },
+    ReceiveExpenseField {
+        expense_id: i64,
+    },
+    ReceiveNewAmount {
+        expense_id: i64,
+    },
+    ReceiveNewNote {
+        expense_id: i64,
+    },
+    ReceiveNewSplitMode {
+        expense_id: i64,
+    },
+    ReceiveNewParticipants {
+        expense_id: i64,
+        split_mode: SplitMode,
+    },
+    // ----- Delete expense
+    ReceiveGroupIdForDeleteExpense,
+    ReceiveExpenseIdForDelete {
+        group_id: i64,
     },
     // ----- Add new group
     ReceiveGroupName,
+    ReceiveGroupCurrency {
+        name: String,
+    },
+    // ----- Change group currency
+    ReceiveGroupIdForSetCurrency,
+    ReceiveNewCurrency {
+        group_id: i64,
+    },
+    // ----- Change debt simplification
+    ReceiveGroupIdForSetSimplification,
+    ReceiveSimplification {
+        group_id: i64,
+    },
+    // ----- Rename, archive or delete a group
+    ReceiveGroupIdForRename,
+    ReceiveNewGroupName {
+        group_id: i64,
+    },
+    ReceiveGroupIdForArchive,
+    ReceiveGroupIdForDelete,
+    ReceiveDeleteConfirmation {
+        group_id: i64,
+    },
+    ReceiveDeleteGroupName {
+        group_id: i64,
+    },
+    // ----- Set exchange rate
+    ReceiveRateEntry,
     // ----- Add memeber to a group
-    ReceiveGroupIdForAddMember,
+    ReceiveGroupIdForAddMember {
+        name: Option<String>,
+    },
     ReceiveUsername {
         group_id: i64,
     },
+    // ----- Invite link
+    ReceiveGroupIdForInvite,
+    ReceiveInviteExpiry {
+        group_id: i64,
+    },
+    ReceiveInviteUses {
+        group_id: i64,
+        days: Option<u32>,
+    },
+    // ----- Merge placeholder member
+    ReceiveGroupIdForMergeMember,
+    ReceivePlaceholder {
+        group_id: i64,
+    },
+    ReceiveMergeTarget {
+        group_id: i64,
+        placeholder_id: i64,
+    },
+    // ----- Promote or demote a member
+    ReceiveGroupIdForRoleChange {
+        role: Role,
+    },
+    ReceiveMemberForRoleChange {
+        group_id: i64,
+        role: Role,
+    },
+    // ----- Leave a group or remove a member from it
+    ReceiveGroupIdForLeave,
+    ReceiveGroupIdForRemoveMember,
+    ReceiveMemberToRemove {
+        group_id: i64,
+    },
+    ReceiveRemovalConfirmation {
+        group_id: i64,
+        user_id: i64,
+    },
     // ----- List expenses in group
     ReceiveGroupIdForExpensesList,
+    // ----- Settle up
+    ReceiveGroupIdForSettleUp,
+    ReceivePaymentRecipient {
+        group_id: i64,
+    },
+    ReceivePaymentAmount {
+        group_id: i64,
+        to_user: i64,
+    },
+    ReceiveGroupIdForSettleAll,
+    // ----- Delete payment
+    ReceiveGroupIdForDeletePayment,
+    ReceivePaymentIdForDelete {
+        group_id: i64,
+    },
 }
 
 lazy_static::lazy_static! {
@@ -73,9 +289,23 @@ pub async fn run() -> anyhow::Result<()> {
         .await
         .apply_migrations()
         .await
-        .expect("Failed to apply database migrations");
+        .map_err(|err| anyhow::anyhow!("Failed to apply database migrations. Err: {err}"))?;
+
+    let storage = DbStorage::<ChatState>::new(
+        DATABASE.get().await,
+        chrono::Duration::minutes(CLI.dialogue_timeout.into()),
+    );
+    let removed = storage
+        .remove_stale()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to remove stale dialogues. Err: {err}"))?;
+    info!("Removed {removed} stale dialogues");
 
-    let bot = Bot::new(&CLI.token);
+    let token = CLI
+        .token
+        .as_deref()
+        .ok_or(anyhow::anyhow!("Bot token is required"))?;
+    let bot = Bot::new(token);
     bot.set_my_commands(Command::bot_commands()).await?;
 
     use dptree::case;
@@ -83,44 +313,259 @@ pub async fn run() -> anyhow::Result<()> {
         .branch(
             case![ChatState::Start]
                 .branch(case![Command::Help].endpoint(help))
-                .branch(case![Command::ListMyGroups].endpoint(list_my_groups))
-                .branch(case![Command::CreateGroup].endpoint(create_group))
-                .branch(case![Command::AddMemberToGroup].endpoint(add_member_to_group))
-                .branch(case![Command::AddExpense].endpoint(add_expense))
+                .branch(case![Command::Start(args)].endpoint(start))
+                .branch(case![Command::ListMyGroups(args)].endpoint(list_my_groups))
+                .branch(case![Command::CreateGroup(args)].endpoint(create_group))
+                .branch(case![Command::SetCurrency].endpoint(set_currency))
+                .branch(case![Command::SetSimplification].endpoint(set_simplification))
+                .branch(case![Command::RenameGroup].endpoint(rename_group))
+                .branch(case![Command::ArchiveGroup].endpoint(archive_group))
+                .branch(case![Command::DeleteGroup].endpoint(delete_group))
+                .branch(case![Command::SetRate].endpoint(set_rate))
+                .branch(case![Command::AddMemberToGroup(args)].endpoint(add_member_to_group))
+                .branch(case![Command::Invite].endpoint(invite))
+                .branch(case![Command::MergeMember].endpoint(merge_member))
+                .branch(case![Command::Promote].endpoint(promote))
+                .branch(case![Command::Demote].endpoint(demote))
+                .branch(case![Command::LeaveGroup].endpoint(leave_group))
+                .branch(case![Command::RemoveMember].endpoint(remove_member))
+                .branch(case![Command::AddExpense(args)].endpoint(add_expense))
+                .branch(case![Command::EditExpense].endpoint(edit_expense))
+                .branch(case![Command::DeleteExpense].endpoint(delete_expense))
                 .branch(case![Command::ListExpensesInGroup].endpoint(list_expenses_in_group))
+                .branch(case![Command::SettleUp].endpoint(settle_up))
+                .branch(case![Command::SettleAll].endpoint(settle_all))
+                .branch(case![Command::DeletePayment].endpoint(delete_payment))
                 .branch(case![Command::Cancel].endpoint(cancel)),
         )
         .branch(case![Command::Cancel].endpoint(cancel));
 
-    let message_handler = Update::filter_message()
+    let message_handler = dptree::entry()
         // ----- Create group
         .branch(case![ChatState::ReceiveGroupName].endpoint(receive_group_name))
+        .branch(case![ChatState::ReceiveGroupCurrency { name }].endpoint(receive_group_currency))
+        // ----- Change group currency
+        .branch(
+            case![ChatState::ReceiveGroupIdForSetCurrency]
+                .endpoint(receive_group_id_for_set_currency),
+        )
+        .branch(case![ChatState::ReceiveNewCurrency { group_id }].endpoint(receive_new_currency))
+        // ----- Change debt simplification
+        .branch(
+            case![ChatState::ReceiveGroupIdForSetSimplification]
+                .endpoint(receive_group_id_for_set_simplification),
+        )
+        .branch(
+            case![ChatState::ReceiveSimplification { group_id }].endpoint(receive_simplification),
+        )
+        // ----- Rename, archive or delete a group
+        .branch(case![ChatState::ReceiveGroupIdForRename].endpoint(receive_group_id_for_rename))
+        .branch(case![ChatState::ReceiveNewGroupName { group_id }].endpoint(receive_new_group_name))
+        .branch(case![ChatState::ReceiveGroupIdForArchive].endpoint(receive_group_id_for_archive))
+        .branch(case![ChatState::ReceiveGroupIdForDelete].endpoint(receive_group_id_for_delete))
+        .branch(
+            case![ChatState::ReceiveDeleteConfirmation { group_id }]
+                .endpoint(receive_delete_confirmation),
+        )
+        .branch(
+            case![ChatState::ReceiveDeleteGroupName { group_id }]
+                .endpoint(receive_delete_group_name),
+        )
+        // ----- Set exchange rate
+        .branch(case![ChatState::ReceiveRateEntry].endpoint(receive_rate_entry))
         // ----- Add member to a group
         .branch(
-            case![ChatState::ReceiveGroupIdForAddMember].endpoint(receive_group_id_for_add_member),
+            case![ChatState::ReceiveGroupIdForAddMember { name }]
+                .endpoint(receive_group_id_for_add_member),
         )
         .branch(case![ChatState::ReceiveUsername { group_id }].endpoint(receive_user_name))
+        // ----- Invite link
+        .branch(case![ChatState::ReceiveGroupIdForInvite].endpoint(receive_group_id_for_invite))
+        .branch(case![ChatState::ReceiveInviteExpiry { group_id }].endpoint(receive_invite_expiry))
+        .branch(
+            case![ChatState::ReceiveInviteUses { group_id, days }].endpoint(receive_invite_uses),
+        )
+        // ----- Merge placeholder member
+        .branch(
+            case![ChatState::ReceiveGroupIdForMergeMember]
+                .endpoint(receive_group_id_for_merge_member),
+        )
+        .branch(case![ChatState::ReceivePlaceholder { group_id }].endpoint(receive_placeholder))
+        .branch(
+            case![ChatState::ReceiveMergeTarget {
+                group_id,
+                placeholder_id
+            }]
+            .endpoint(receive_merge_target),
+        )
+        // ----- Promote or demote a member
+        .branch(
+            case![ChatState::ReceiveGroupIdForRoleChange { role }]
+                .endpoint(receive_group_id_for_role_change),
+        )
+        .branch(
+            case![ChatState::ReceiveMemberForRoleChange { group_id, role }]
+                .endpoint(receive_member_for_role_change),
+        )
+        // ----- Leave a group or remove a member from it
+        .branch(case![ChatState::ReceiveGroupIdForLeave].endpoint(receive_group_id_for_leave))
+        .branch(
+            case![ChatState::ReceiveGroupIdForRemoveMember]
+                .endpoint(receive_group_id_for_remove_member),
+        )
+        .branch(
+            case![ChatState::ReceiveMemberToRemove { group_id }].endpoint(receive_member_to_remove),
+        )
+        .branch(
+            case![ChatState::ReceiveRemovalConfirmation { group_id, user_id }]
+                .endpoint(receive_removal_confirmation),
+        )
         // ----- Add expense
-        .branch(case![ChatState::ReceiveGroupIdForExpense].endpoint(receive_group_id_for_expense))
+        .branch(
+            case![ChatState::ReceiveGroupIdForExpense { draft }]
+                .endpoint(receive_group_id_for_expense),
+        )
         .branch(case![ChatState::RecieveAmountSpent { group_id }].endpoint(receive_amount_spent))
-        .branch(case![ChatState::ReceiveNote { group_id, amount }].endpoint(receive_note))
+        .branch(
+            case![ChatState::ReceiveExchangeRate {
+                group_id,
+                amount,
+                currency,
+                note
+            }]
+            .endpoint(receive_exchange_rate),
+        )
+        .branch(
+            case![ChatState::ReceiveNote {
+                group_id,
+                amount,
+                currency,
+                exchange_rate
+            }]
+            .endpoint(receive_note),
+        )
+        .branch(
+            case![ChatState::ReceivePayer {
+                group_id,
+                amount,
+                currency,
+                exchange_rate,
+                note
+            }]
+            .endpoint(receive_payer),
+        )
+        .branch(
+            case![ChatState::ReceiveSplitMode {
+                group_id,
+                amount,
+                currency,
+                exchange_rate,
+                note,
+                payers
+            }]
+            .endpoint(receive_split_mode),
+        )
+        .branch(
+            case![ChatState::ReceiveParticipants {
+                group_id,
+                amount,
+                currency,
+                exchange_rate,
+                note,
+                payers,
+                split_mode
+            }]
+            .endpoint(receive_participants),
+        )
+        // ----- Edit expense
+        .branch(
+            case![ChatState::ReceiveGroupIdForEditExpense]
+                .endpoint(receive_group_id_for_edit_expense),
+        )
+        .branch(
+            case![ChatState::ReceiveExpenseIdForEdit { group_id }]
+                .endpoint(receive_expense_id_for_edit),
+        )
+        .branch(
+            case![ChatState::ReceiveExpenseField { expense_id }].endpoint(receive_expense_field),
+        )
+        .branch(case![ChatState::ReceiveNewAmount { expense_id }].endpoint(receive_new_amount))
+        .branch(case![ChatState::ReceiveNewNote { expense_id }].endpoint(receive_new_note))
+        .branch(
+            case![ChatState::ReceiveNewSplitMode { expense_id }].endpoint(receive_new_split_mode),
+        )
+        .branch(
+            case![ChatState::ReceiveNewParticipants {
+                expense_id,
+                split_mode
+            }]
+            .endpoint(receive_new_participants),
+        )
+        // ----- Delete expense
+        .branch(
+            case![ChatState::ReceiveGroupIdForDeleteExpense]
+                .endpoint(receive_group_id_for_delete_expense),
+        )
+        .branch(
+            case![ChatState::ReceiveExpenseIdForDelete { group_id }]
+                .endpoint(receive_expense_id_for_delete),
+        )
         // ----- List expenses in group
         .branch(
             case![ChatState::ReceiveGroupIdForExpensesList]
                 .endpoint(receive_group_id_for_expenses_list),
+        )
+        // ----- Settle up
+        .branch(
+            case![ChatState::ReceiveGroupIdForSettleUp].endpoint(receive_group_id_for_settle_up),
+        )
+        .branch(
+            case![ChatState::ReceivePaymentRecipient { group_id }]
+                .endpoint(receive_payment_recipient),
+        )
+        .branch(
+            case![ChatState::ReceivePaymentAmount { group_id, to_user }]
+                .endpoint(receive_payment_amount),
+        )
+        .branch(
+            case![ChatState::ReceiveGroupIdForSettleAll].endpoint(receive_group_id_for_settle_all),
+        )
+        // ----- Delete payment
+        .branch(
+            case![ChatState::ReceiveGroupIdForDeletePayment]
+                .endpoint(receive_group_id_for_delete_payment),
+        )
+        .branch(
+            case![ChatState::ReceivePaymentIdForDelete { group_id }]
+                .endpoint(receive_payment_id_for_delete),
         );
 
-    let composed_handler = Update::filter_message()
-        .branch(command_handler)
-        .branch(message_handler)
+    // Buttons of inline keyboards answer the same questions typed messages do
+    let callback_handler = Update::filter_callback_query()
+        .filter_map_async(callback_to_message)
+        .inspect_async(enroll_chat_members)
+        .branch(dptree::filter_map(parse_settle_all_button).endpoint(settle_all_button))
+        .branch(command_handler.clone())
+        .branch(message_handler.clone())
         .branch(dptree::endpoint(invalid_state));
 
-    let handler =
-        dialogue::enter::<Update, InMemStorage<ChatState>, ChatState, _>().branch(composed_handler);
+    let composed_handler = dptree::entry()
+        .branch(
+            Update::filter_message()
+                .inspect_async(enroll_chat_members)
+                .branch(command_handler)
+                .branch(message_handler)
+                .branch(dptree::endpoint(invalid_state)),
+        )
+        .branch(callback_handler);
+
+    let handler = enter_dialogue()
+        .chain(denial_handler())
+        .branch(composed_handler);
 
     info!("Ready for listening commands hand messages...");
     Dispatcher::builder(bot, handler)
-        .dependencies(dptree::deps![InMemStorage::<ChatState>::new()])
+        .dependencies(dptree::deps![storage])
         .error_handler(LoggingErrorHandler::with_custom_text(
             "An error has occurred in the dispatcher",
         ))
@@ -132,141 +577,443 @@ pub async fn run() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Like `dialogue::enter` of teloxide, but provides the dialogue of whoever sent the update rather than
+/// the one of the whole chat, along with its state
+fn enter_dialogue() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync>> {
+    dptree::filter_map(|storage: Arc<DbStorage<ChatState>>, update: Update| {
+        Some(MyDialogue::new(
+            storage,
+            update.chat()?.id,
+            update.user()?.id,
+        ))
+    })
+    .filter_map_async(|dialogue: MyDialogue| async move {
+        match dialogue.get_or_default().await {
+            Ok(state) => Some(state),
+            Err(err) => {
+                tracing::error!(?err, "Error occurred while retrieving a dialogue");
+                None
+            }
+        }
+    })
+}
+
+/// Turns a tap on an inline keyboard button into a message from whoever tapped it, with the data of the
+/// button as its text, so that buttons go through the same handlers as typed answers
+async fn callback_to_message(bot: Bot, q: CallbackQuery, state: ChatState) -> Option<Message> {
+    // Stops the loading animation of the button
+    if let Err(err) = bot.answer_callback_query(&q.id).await {
+        tracing::error!(?err, "Error occurred while answering a callback query");
+    }
+
+    let message = q.message?;
+    let msg = message_from(message.clone(), q.from, q.data?);
+
+    // The choice has been made, so the buttons can't be tapped once again. In group chats the buttons
+    // stay for whoever they were meant for, if the tap doesn't answer anything the tapper was asked
+    let answers =
+        !matches!(state, ChatState::Start) || parse_settle_all_button(msg.clone()).is_some();
+    if answers || !is_group_chat(&msg) {
+        if let Err(err) = bot
+            .edit_message_reply_markup(message.chat.id, message.id)
+            .await
+        {
+            tracing::error!(?err, "Error occurred while removing an inline keyboard");
+        }
+    }
+
+    Some(msg)
+}
+
+/// Plain text message from `from`, sent in the chat of `message`
+fn message_from(message: Message, from: User, text: String) -> Message {
+    let kind = MessageKind::Common(MessageCommon {
+        from: Some(from),
+        sender_chat: None,
+        author_signature: None,
+        forward: None,
+        reply_to_message: None,
+        edit_date: None,
+        media_kind: MediaKind::Text(MediaText {
+            text,
+            entities: Vec::new(),
+        }),
+        reply_markup: None,
+        is_topic_message: false,
+        is_automatic_forward: false,
+        has_protected_content: false,
+    });
+
+    Message { kind, ..message }
+}
+
+/// The message the author would send to answer which group they mean, in a chat that is bound to one
+fn group_answer(
+    msg: &Message,
+    group: &group::Model,
+) -> Result<Message, Box<dyn std::error::Error + Send + Sync>> {
+    let from = msg
+        .from()
+        .cloned()
+        .ok_or("😔Sorry, I can't get info about you😔")?;
+    Ok(message_from(msg.clone(), from, group.id.to_string()))
+}
+
+/// Members of a chat bound to a group join the group as soon as they show up in the chat
+async fn enroll_chat_members(bot: Bot, msg: Message) {
+    let users = msg
+        .from()
+        .into_iter()
+        .chain(msg.new_chat_members().unwrap_or_default())
+        .filter(|x| !x.is_bot);
+
+    for user in users {
+        if let Err(err) = enroll_chat_member(&bot, &msg, user).await {
+            tracing::error!(?err, "Error occurred while enrolling a chat member");
+        }
+    }
+}
+
+async fn enroll_chat_member(bot: &Bot, msg: &Message, user: &User) -> anyhow::Result<()> {
+    let ctl = Controller::new(bot, msg.chat.id, user.id).await?;
+    if ctl.get_chat_group().await?.is_none() {
+        return Ok(());
+    }
+
+    let username = user.username.as_ref().map(|x| format!("@{}", x));
+    ctl.sync_user(username.as_deref(), &user.full_name())
+        .await?;
+    ctl.join_chat_group().await
+}
+
 async fn help(bot: Bot, msg: Message) -> HandlerResult {
     bot.send_message(msg.chat.id, Command::descriptions().to_string())
         .await?;
     Ok(())
 }
 
+/// `/start` comes with a payload when the bot is opened by a deep link, which is an invite token
+async fn start(bot: Bot, dialogue: MyDialogue, msg: Message, args: String) -> HandlerResult {
+    let token = args.trim();
+    if token.is_empty() {
+        return help(bot, msg).await;
+    }
+
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    // Joins with the Telegram account that opened the link, claiming a placeholder of the same
+    // @username if there is one
+    get_author(&ctl, &msg).await?;
+
+    let text = match ctl.join_by_invite(token).await {
+        Ok((group, true)) => format!("Welcome! You've joined the group `{}`", group.name),
+        Ok((group, false)) => format!("You're a member of `{}` already", group.name),
+        Err(err) => match err.downcast_ref::<InviteError>() {
+            Some(err) => format!("😔{}😔", err),
+            None => return Err(err.into()),
+        },
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    dialogue.update(ChatState::Start).await?;
+
+    Ok(())
+}
+
 fn groups_to_pretty(groups: Vec<group::Model>) -> String {
     groups
         .iter()
-        .map(|model| format!("{} — `{}`\n", model.id, model.name))
+        .map(|model| {
+            let archived = if model.is_archived() {
+                " (archived)"
+            } else {
+                ""
+            };
+            format!("{} — `{}`{}\n", model.id, model.name, archived)
+        })
         .collect::<Vec<String>>()
         .join(", ")
 }
 
-async fn list_my_groups(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
-    let username = get_author_username(&msg).await?;
-    let ctl = Controller::from_msg(&bot, &msg).await?;
+/// Inline keyboard with a button per row. Tapping a button sends its data, just as if it was typed
+fn keyboard(buttons: impl IntoIterator<Item = (String, String)>) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(
+        buttons
+            .into_iter()
+            .map(|(label, data)| vec![InlineKeyboardButton::callback(label, data)]),
+    )
+}
 
-    let groups = ctl.get_user_groups(&username).await?;
-    if groups.is_empty() {
-        bot.send_message(msg.chat.id, "You don't belong to any group yet")
-            .await?;
-        dialogue.update(ChatState::Start).await?;
-    } else {
-        let groups = groups_to_pretty(groups);
-        let text = format!("Here are your groups:\n {}", groups);
+/// Asks for a typed answer. With privacy mode on, the bot receives only commands and replies to its
+/// own messages from group chats, so in there the app is told to reply to the question
+trait Prompt {
+    fn prompt(self, msg: &Message) -> Self;
+}
 
-        bot.send_message(msg.chat.id, text).await?;
+impl Prompt for JsonRequest<SendMessage> {
+    fn prompt(self, msg: &Message) -> Self {
+        match is_group_chat(msg) {
+            true => self.reply_markup(ForceReply::new()),
+            false => self,
+        }
     }
-
-    Ok(())
 }
 
-async fn create_group(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
-    let text = "Pick a name for your group";
-    bot.send_message(msg.chat.id, text).await?;
-    dialogue.update(ChatState::ReceiveGroupName).await?;
-    Ok(())
+/// Buttons that send the option itself
+fn options_keyboard(options: &[&str]) -> InlineKeyboardMarkup {
+    keyboard(options.iter().map(|x| (x.to_string(), x.to_string())))
 }
 
-async fn receive_group_name(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
-    if let Some(group_name) = msg.text() {
-        let username = get_author_username(&msg).await?;
-
-        let ctl = Controller::from_msg(&bot, &msg).await?;
-        let cretaed_group = ctl.create_group(group_name).await?;
-        ctl.add_user_to_a_group(&username, cretaed_group.id).await?;
+/// Buttons that send the id of a group
+fn groups_keyboard(groups: &[group::Model]) -> InlineKeyboardMarkup {
+    keyboard(groups.iter().map(|x| (x.name.clone(), x.id.to_string())))
+}
 
-        let text = format!(
-            "Group `{}` was successfully created and you've been added to it",
-            group_name
-        );
-        bot.send_message(dialogue.chat_id(), text).await?;
-        dialogue.update(ChatState::Start).await?;
-    }
+/// Buttons that send the id of a member as `#<id>`, so that a tap picks the member it shows even
+/// if the list has changed since
+fn members_keyboard(members: &[user::Model]) -> InlineKeyboardMarkup {
+    keyboard(members.iter().map(|x| (x.mention(), format!("#{}", x.id))))
+}
 
-    Ok(())
+/// Buttons that send the id of an expense
+fn expenses_keyboard(expenses: &[expense::Model]) -> InlineKeyboardMarkup {
+    keyboard(expenses.iter().map(|x| {
+        (
+            format!("{} {} — {}", x.amount, x.currency, x.note),
+            x.id.to_string(),
+        )
+    }))
 }
 
-async fn add_member_to_group(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
-    let username = get_author_username(&msg).await?;
+const SPLIT_MODES: &[&str] = &["equal", "exact", "percent", "shares"];
+
+/// Archived groups are left out, unless `/listmygroups all` asks for them
+async fn list_my_groups(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    args: String,
+) -> HandlerResult {
     let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
 
-    let groups = ctl.get_user_groups(&username).await?;
+    let mut groups = ctl.get_all_user_groups(author.id).await?;
     if groups.is_empty() {
         bot.send_message(msg.chat.id, "You don't belong to any group yet")
             .await?;
-
         dialogue.update(ChatState::Start).await?;
-    } else {
-        let groups = groups_to_pretty(groups);
-        let text = format!(
-            "Choose id of the group where you want to add a member:\n {}",
-            groups
-        );
+        return Ok(());
+    }
 
-        bot.send_message(msg.chat.id, text).await?;
+    let total = groups.len();
+    if !args.trim().eq_ignore_ascii_case("all") {
+        groups.retain(|x| !x.is_archived());
+    }
+    let hidden = total - groups.len();
 
-        dialogue
-            .update(ChatState::ReceiveGroupIdForAddMember)
-            .await?;
+    let mut text = if groups.is_empty() {
+        String::from("All of your groups are archived")
+    } else {
+        format!("Here are your groups:\n {}", groups_to_pretty(groups))
+    };
+    if hidden > 0 {
+        text.push_str(&format!(
+            "\n{} archived groups are hidden, `/listmygroups all` shows them",
+            hidden
+        ));
     }
+    bot.send_message(msg.chat.id, text).await?;
 
     Ok(())
 }
 
-async fn add_expense(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
-    let username = get_author_username(&msg).await?;
-    let ctl = Controller::from_msg(&bot, &msg).await?;
-
-    let groups = ctl.get_user_groups(&username).await?;
-    if groups.is_empty() {
-        bot.send_message(msg.chat.id, "You don't belong to any group yet")
-            .await?;
-        dialogue.update(ChatState::Start).await?;
-    } else {
-        let groups = groups_to_pretty(groups);
-        let text = format!(
-            "Choose id of the group you'd like to add the expense:\n {}",
-            groups
-        );
-        bot.send_message(msg.chat.id, text).await?;
-        dialogue.update(ChatState::ReceiveGroupIdForExpense).await?;
+/// Splits `<name> [currency]`. Only an upper case ISO 4217 code is taken for a currency, so that
+/// names like `Spa` or `Trip to NYC` stay names
+fn parse_group_args(text: &str) -> (String, Option<String>) {
+    let text = text.trim();
+    match text.rsplit_once(char::is_whitespace) {
+        Some((name, code)) if settlement::is_currency(code) => {
+            (name.trim().to_owned(), Some(code.to_owned()))
+        }
+        _ => (text.to_owned(), None),
     }
-
-    Ok(())
 }
 
-async fn receive_group_id_for_expense(
-    bot: Bot,
-    msg: Message,
-    dialogue: MyDialogue,
-) -> HandlerResult {
-    if let Some(group_id) = msg.text() {
-        if let Ok(group_id) = group_id.parse::<i64>() {
-            let ctl = Controller::from_msg(&bot, &msg).await?;
+/// Whether the message was sent in a Telegram group chat, which groups created there are bound to
+fn is_group_chat(msg: &Message) -> bool {
+    msg.chat.is_group() || msg.chat.is_supergroup()
+}
 
-            let group_name = ctl
-                .get_group_by_id(group_id)
-                .await?
-                .ok_or(anyhow::anyhow!("Inexistent group id"))?
-                .name;
+async fn create_group(bot: Bot, dialogue: MyDialogue, msg: Message, args: String) -> HandlerResult {
+    let (mut name, currency) = parse_group_args(&args);
 
-            let msg = format!(
-                "You want to add an expense to a group `{}`.\n Now, type the amount you spent:",
-                group_name
-            );
+    if is_group_chat(&msg) {
+        let ctl = Controller::from_msg(&bot, &msg).await?;
+        if let Some(group) = ctl.get_chat_group().await? {
+            let text = format!("This chat already has the group `{}`", group.name);
+            bot.send_message(msg.chat.id, text).await?;
+            return Ok(());
+        }
 
-            bot.send_message(dialogue.chat_id(), msg).await?;
+        // Named after the chat unless told otherwise
+        if name.is_empty() {
+            name = msg.chat.title().unwrap_or_default().to_owned();
+        }
+    }
 
-            dialogue
-                .update(ChatState::RecieveAmountSpent { group_id })
-                .await?;
+    match (name, currency) {
+        (name, _) if name.is_empty() => {
+            let text = "Pick a name for your group";
+            bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+            dialogue.update(ChatState::ReceiveGroupName).await?;
+        }
+        (name, Some(currency)) => {
+            create_group_in(&bot, &dialogue, &msg, &name, &currency).await?;
+        }
+        (name, None) => {
+            ask_group_currency(&bot, &dialogue, &msg, name).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn ask_group_currency(
+    bot: &Bot,
+    dialogue: &MyDialogue,
+    msg: &Message,
+    name: String,
+) -> HandlerResult {
+    bot.send_message(
+        msg.chat.id,
+        "Which currency does the group use? Send its code, e.g. `EUR`:",
+    )
+    .prompt(msg)
+    .await?;
+    dialogue
+        .update(ChatState::ReceiveGroupCurrency { name })
+        .await?;
+
+    Ok(())
+}
+
+async fn create_group_in(
+    bot: &Bot,
+    dialogue: &MyDialogue,
+    msg: &Message,
+    group_name: &str,
+    currency: &str,
+) -> HandlerResult {
+    let ctl = Controller::from_msg(bot, msg).await?;
+    // The creator becomes the first member, so they must be known first
+    get_author(&ctl, msg).await?;
+
+    let bind_chat = is_group_chat(msg);
+    ctl.create_group(group_name, currency, bind_chat).await?;
+
+    let text = if bind_chat {
+        format!(
+            "Group `{}` was successfully created for this chat. Everybody who joins the chat or talks to the bot here joins it, unless they have left it or were removed",
+            group_name
+        )
+    } else {
+        format!(
+            "Group `{}` was successfully created and you've been added to it",
+            group_name
+        )
+    };
+    bot.send_message(dialogue.chat_id(), text).await?;
+    dialogue.update(ChatState::Start).await?;
+
+    Ok(())
+}
+
+async fn receive_group_name(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
+    if let Some(group_name) = msg.text() {
+        ask_group_currency(&bot, &dialogue, &msg, group_name.to_owned()).await?;
+    }
+
+    Ok(())
+}
+
+/// Parses an ISO 4217 currency code, e.g. `eur` or `USD`. Made-up codes like `ABC` are refused
+fn parse_currency(text: &str) -> Option<String> {
+    Some(text.trim().to_ascii_uppercase()).filter(|x| settlement::is_currency(x))
+}
+
+async fn receive_group_currency(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_name: String,
+) -> HandlerResult {
+    if let Some(text) = msg.text() {
+        if let Some(currency) = parse_currency(text) {
+            create_group_in(&bot, &dialogue, &msg, &group_name, &currency).await?;
         } else {
-            bot.send_message(msg.chat.id, "Please, send a decimal value")
+            bot.send_message(
+                msg.chat.id,
+                "Please, send an ISO 4217 currency code, e.g. `EUR`:",
+            )
+            .prompt(&msg)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn set_currency(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_set_currency(bot, dialogue, answer).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group which currency you'd like to change:\n {}",
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue
+            .update(ChatState::ReceiveGroupIdForSetCurrency)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn receive_group_id_for_set_currency(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+
+            let group = ctl.authorize_admin(group_id).await?;
+            let text = format!(
+                "The group uses {} now. Send the code of the new currency:",
+                group.currency
+            );
+            bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+            dialogue
+                .update(ChatState::ReceiveNewCurrency { group_id })
+                .await?;
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
                 .await?;
         }
     }
@@ -274,13 +1021,79 @@ async fn receive_group_id_for_expense(
     Ok(())
 }
 
-#[derive(Debug)]
-struct UserDebt {
-    username: String,
-    debt: Decimal,
+async fn receive_new_currency(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    if let Some(text) = msg.text() {
+        if let Some(currency) = parse_currency(text) {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+
+            let text = match ctl.set_group_currency(group_id, &currency).await? {
+                Some(group) => format!("Group `{}` uses {} now", group.name, group.currency),
+                None => String::from(
+                    "😔The group already has expenses or payments, so its currency can't be changed anymore😔",
+                ),
+            };
+            bot.send_message(msg.chat.id, text).await?;
+
+            dialogue.update(ChatState::Start).await?;
+        } else {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send an ISO 4217 currency code, e.g. `EUR`:",
+            )
+            .prompt(&msg)
+            .await?;
+        }
+    }
+
+    Ok(())
 }
 
-async fn receive_group_id_for_expenses_list(
+async fn set_simplification(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_set_simplification(bot, dialogue, answer).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group which debt simplification you'd like to change:\n {}",
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue
+            .update(ChatState::ReceiveGroupIdForSetSimplification)
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn simplification_to_pretty(simplification: Simplification) -> &'static str {
+    match simplification {
+        Simplification::Greedy => "greedy",
+        Simplification::Minimal => "minimal",
+        Simplification::Pairwise => "none",
+    }
+}
+
+async fn receive_group_id_for_set_simplification(
     bot: Bot,
     dialogue: MyDialogue,
     msg: Message,
@@ -288,164 +1101,2778 @@ async fn receive_group_id_for_expenses_list(
     if let Some(group_id) = msg.text() {
         if let Ok(group_id) = group_id.parse::<i64>() {
             let ctl = Controller::from_msg(&bot, &msg).await?;
-            let expenses_in_group = ctl.get_expenses_in_group(group_id).await?;
 
-            if !expenses_in_group.is_empty() {
-                let mut user_spent: HashMap<String, Decimal> = HashMap::new();
+            let group = ctl.authorize_admin(group_id).await?;
+            let text = format!(
+                "The group uses `{}` simplification now. Choose a new one:\n\
+                 greedy - the largest debtors pay the largest creditors first\n\
+                 minimal - as few transfers as possible\n\
+                 none - everybody pays back only those who paid for them",
+                simplification_to_pretty(group.simplification)
+            );
+            bot.send_message(msg.chat.id, text)
+                .reply_markup(options_keyboard(&["greedy", "minimal", "none"]))
+                .await?;
+            dialogue
+                .update(ChatState::ReceiveSimplification { group_id })
+                .await?;
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
 
-                for exp in expenses_in_group.iter() {
-                    *user_spent.entry(exp.username.clone()).or_default() += exp.amount;
-                }
+async fn receive_simplification(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    if let Some(text) = msg.text() {
+        let simplification = match text.trim().to_lowercase().as_str() {
+            "greedy" => Some(Simplification::Greedy),
+            "minimal" => Some(Simplification::Minimal),
+            "none" | "pairwise" => Some(Simplification::Pairwise),
+            _ => None,
+        };
+
+        if let Some(simplification) = simplification {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            let group = ctl
+                .set_group_simplification(group_id, simplification)
+                .await?;
+
+            let text = format!(
+                "Group `{}` uses `{}` simplification now",
+                group.name,
+                simplification_to_pretty(group.simplification)
+            );
+            bot.send_message(msg.chat.id, text).await?;
 
-                // How much many was spent overall
-                let sum: Decimal = user_spent.iter().map(|x| *x.1).sum();
-                assert!(!user_spent.is_empty());
+            dialogue.update(ChatState::Start).await?;
+        } else {
+            bot.send_message(msg.chat.id, "Please, send `greedy`, `minimal` or `none`:")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
 
-                // Mean spent per user in a group
-                let mean = sum / Decimal::from(user_spent.len());
+async fn rename_group(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_rename(bot, dialogue, answer).await;
+    }
 
-                // How much everybody owes to the group. Negative value means that this person is owed by the group
-                let user_debt: Vec<(String, Decimal)> =
-                    user_spent.into_iter().map(|x| (x.0, x.1 - mean)).collect();
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!("Choose id of the group you'd like to rename:\n {}", groups);
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue.update(ChatState::ReceiveGroupIdForRename).await?;
+    }
 
-                // Debt must sum up to zero
-                debug_assert!(user_debt.iter().map(|x| x.1).sum::<Decimal>() == Decimal::from(0));
+    Ok(())
+}
 
-                let mut creditors: Vec<UserDebt> = Vec::new();
-                let mut debitors: Vec<UserDebt> = Vec::new();
-                let mut transactions: Vec<(String, String, Decimal)> = Vec::new();
+async fn receive_group_id_for_rename(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            let group = ctl.authorize_admin(group_id).await?;
 
-                // Separate users into creditors and debitors
-                for (username, debt) in user_debt {
-                    match debt.cmp(&Decimal::ZERO) {
-                        Ordering::Greater => {
-                            creditors.push(UserDebt {
-                                username: username.clone(),
-                                debt: debt.abs(),
-                            });
-                        }
-                        Ordering::Less => {
-                            debitors.push(UserDebt {
-                                username: username.clone(),
-                                debt: debt.abs(),
-                            });
-                        }
-                        _ => (),
-                    }
-                }
+            let text = format!("Send a new name for `{}`:", group.name);
+            bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+            dialogue
+                .update(ChatState::ReceiveNewGroupName { group_id })
+                .await?;
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
 
-                // Sort creditors and debtors by debt amount
-                creditors.sort_by(|a, b| b.debt.cmp(&a.debt));
-                debitors.sort_by(|a, b| b.debt.cmp(&a.debt));
+    Ok(())
+}
 
-                // Match debtors and creditors
-                let mut debitor_index = 0;
-                let mut creditor_index = 0;
+async fn receive_new_group_name(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    if let Some(name) = msg.text().map(str::trim) {
+        if name.is_empty() {
+            bot.send_message(msg.chat.id, "Please, send a name:")
+                .prompt(&msg)
+                .await?;
+            return Ok(());
+        }
 
-                while debitor_index < debitors.len() && creditor_index < creditors.len() {
-                    let debtor = &debitors[debitor_index];
-                    let creditor = &creditors[creditor_index];
+        let ctl = Controller::from_msg(&bot, &msg).await?;
+        let group = ctl.rename_group(group_id, name).await?;
 
-                    // Calculate the amount to transfer
-                    let transfer_amount = debtor.debt.min(creditor.debt);
+        let text = format!("The group is called `{}` now", group.name);
+        bot.send_message(msg.chat.id, text).await?;
+        dialogue.update(ChatState::Start).await?;
+    }
 
-                    // Record the transaction
-                    transactions.push((
-                        debtor.username.clone(),
-                        creditor.username.clone(),
-                        transfer_amount,
-                    ));
+    Ok(())
+}
 
-                    // Adjust the debt values
-                    debitors[debitor_index].debt -= transfer_amount;
-                    creditors[creditor_index].debt -= transfer_amount;
+/// Archived groups are listed too, since that's the way to bring them back
+async fn archive_group(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
 
-                    // If a debtor's debt is fully matched, move to the next debitor
-                    if debitors[debitor_index].debt == Decimal::ZERO {
-                        debitor_index += 1;
-                    }
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_archive(bot, dialogue, answer).await;
+    }
 
-                    // If a creditor's debt is fully matched, move to the next creditor
-                    if creditors[creditor_index].debt == Decimal::ZERO {
-                        creditor_index += 1;
-                    }
-                }
+    let groups = ctl.get_all_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group you'd like to archive, or of an archived one to bring it back:\n {}",
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue.update(ChatState::ReceiveGroupIdForArchive).await?;
+    }
+
+    Ok(())
+}
+
+async fn receive_group_id_for_archive(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            let group = ctl.authorize_admin_incl_archived(group_id).await?;
+            let group = ctl
+                .set_group_archived(group_id, !group.is_archived())
+                .await?;
+
+            let text = if group.is_archived() {
+                format!(
+                    "Group `{}` is archived now. Nothing can be changed in it and it's hidden from \
+                     your groups, but `/listmygroups all` still shows it. Send /archivegroup again \
+                     to bring it back",
+                    group.name
+                )
+            } else {
+                format!("Group `{}` is back in use", group.name)
+            };
+            bot.send_message(msg.chat.id, text).await?;
+            dialogue.update(ChatState::Start).await?;
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn delete_group(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_delete(bot, dialogue, answer).await;
+    }
+
+    let groups = ctl.get_all_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!("Choose id of the group you'd like to delete:\n {}", groups);
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue.update(ChatState::ReceiveGroupIdForDelete).await?;
+    }
+
+    Ok(())
+}
+
+/// Deleting a group takes two confirmations: `yes` first, then the name of the group
+async fn receive_group_id_for_delete(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            let group = ctl.authorize_admin_incl_archived(group_id).await?;
+            let ledger = ctl.get_ledger(group_id).await?;
+
+            let text = format!(
+                "Deleting `{}` removes its {} expenses and {} payments for all of its {} members. \
+                 It can't be undone, archiving keeps everything instead. Send `yes` to delete it or `no` to keep it:",
+                group.name,
+                ledger.expenses.len(),
+                ledger.payments.len(),
+                ledger.members.len()
+            );
+            bot.send_message(msg.chat.id, text)
+                .reply_markup(options_keyboard(&["yes", "no"]))
+                .await?;
+            dialogue
+                .update(ChatState::ReceiveDeleteConfirmation { group_id })
+                .await?;
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_delete_confirmation(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    if let Some(text) = msg.text() {
+        match text.trim().to_lowercase().as_str() {
+            "yes" => {
+                let ctl = Controller::from_msg(&bot, &msg).await?;
+                let group = ctl.authorize_admin_incl_archived(group_id).await?;
+
+                let text = format!("To confirm, send the name of the group, `{}`:", group.name);
+                bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+                dialogue
+                    .update(ChatState::ReceiveDeleteGroupName { group_id })
+                    .await?;
+            }
+            "no" => {
+                bot.send_message(msg.chat.id, "Nothing has changed").await?;
+                dialogue.update(ChatState::Start).await?;
+            }
+            _ => {
+                bot.send_message(msg.chat.id, "Please, send `yes` or `no`:")
+                    .prompt(&msg)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_delete_group_name(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    if let Some(name) = msg.text() {
+        let ctl = Controller::from_msg(&bot, &msg).await?;
+        let group = ctl.authorize_admin_incl_archived(group_id).await?;
+
+        let text = if name.trim() == group.name {
+            ctl.delete_group(group_id).await?;
+            format!("Group `{}` has been deleted", group.name)
+        } else {
+            String::from("The name doesn't match, so the group stays")
+        };
+        bot.send_message(msg.chat.id, text).await?;
+        dialogue.update(ChatState::Start).await?;
+    }
+
+    Ok(())
+}
+
+async fn add_member_to_group(
+    bot: Bot,
+    msg: Message,
+    dialogue: MyDialogue,
+    args: String,
+) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+    let name = Some(args.trim().to_owned()).filter(|x| !x.is_empty());
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_add_member(bot, dialogue, answer, name).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group where you want to add a member:\n {}",
+            groups
+        );
+
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+
+        dialogue
+            .update(ChatState::ReceiveGroupIdForAddMember { name })
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Parses `<amount> [currency] [note]`. Only an upper case ISO 4217 code is taken for a currency,
+/// so that notes like `tea` or `BBQ at the park` stay notes
+fn parse_expense_args(text: &str) -> Option<ExpenseDraft> {
+    let (amount, rest) = text
+        .trim()
+        .split_once(char::is_whitespace)
+        .unwrap_or((text.trim(), ""));
+    let amount = amount.parse::<Decimal>().ok()?;
+
+    let rest = rest.trim();
+    let (code, note) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let (currency, note) = if settlement::is_currency(code) {
+        (Some(code.to_owned()), note.trim())
+    } else {
+        (None, rest)
+    };
+
+    Some(ExpenseDraft {
+        amount,
+        currency,
+        note: Some(note.to_owned()).filter(|x| !x.is_empty()),
+    })
+}
+
+async fn add_expense(bot: Bot, msg: Message, dialogue: MyDialogue, args: String) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    let draft = if args.trim().is_empty() {
+        None
+    } else if let Some(draft) = parse_expense_args(&args) {
+        Some(draft)
+    } else {
+        bot.send_message(
+            msg.chat.id,
+            "Please, start with the amount, e.g. `/addexpense 12.50 pizza` or `/addexpense 12.50 USD pizza`",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_expense(bot, answer, dialogue, draft).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group you'd like to add the expense:\n {}",
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue
+            .update(ChatState::ReceiveGroupIdForExpense { draft })
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn receive_group_id_for_expense(
+    bot: Bot,
+    msg: Message,
+    dialogue: MyDialogue,
+    draft: Option<ExpenseDraft>,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+
+            let group = ctl.authorize_group(group_id).await?;
+
+            match draft {
+                Some(draft) => accept_amount(&bot, &dialogue, &msg, group, draft).await?,
+                None => {
+                    let text = format!(
+                        "You want to add an expense to a group `{}`.\n Now, type the amount you spent, e.g. `12.50` for {} or `12.50 USD` for another currency:",
+                        group.name, group.currency
+                    );
+
+                    bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+
+                    dialogue
+                        .update(ChatState::RecieveAmountSpent { group_id })
+                        .await?;
+                }
+            }
+        } else {
+            bot.send_message(msg.chat.id, "Please, send a decimal value")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_group_id_for_expenses_list(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            let ledger = ctl.get_ledger(group_id).await?;
+            // History may mention those who have left the group since
+            let people: Vec<user::Model> = ledger
+                .members
+                .iter()
+                .chain(ledger.former_members.iter())
+                .cloned()
+                .collect();
+
+            if !ledger.expenses.is_empty() {
+                let transactions = settlement::debts(&ledger)?;
+
+                let mut text = String::from("Group debt state:\n");
+
+                if transactions.is_empty() {
+                    text.push_str("😊No debt in this group😊");
+                }
+
+                text.push_str(&transfers_to_pretty(
+                    &people,
+                    &transactions,
+                    &ledger.currency,
+                ));
+
+                if !transactions.is_empty() {
+                    text.push_str(
+                        "Once these are paid, tap the button or send /settleall to mark all of them as settled\n",
+                    );
+                }
+
+                // Same debts without conversion, for those who'd rather pay back in the original currency
+                let by_currency = settlement::debts_by_currency(&ledger)?;
+                if by_currency.len() > 1 {
+                    text.push_str("\n --- \n Debts per currency, without conversion:\n");
+
+                    for (currency, transfers) in by_currency.iter() {
+                        for transfer in transfers {
+                            let formatted_string = format!(
+                                "{} owes {} {} to {}\n",
+                                member_name(&people, transfer.from),
+                                transfer.amount,
+                                currency,
+                                member_name(&people, transfer.to)
+                            );
+                            text.push_str(&formatted_string);
+                        }
+                    }
+                }
+
+                text.push_str("\n --- \n Overall expenses in group:\n");
+
+                for exp in ledger.expenses.iter() {
+                    let sharing: Vec<String> = ledger
+                        .participants
+                        .iter()
+                        .filter(|x| x.expense_id == exp.id)
+                        .map(|x| match x.share {
+                            Some(share) => {
+                                format!("{} ({})", member_name(&people, x.user_id), share)
+                            }
+                            None => member_name(&people, x.user_id),
+                        })
+                        .collect();
+                    let sharing = if sharing.is_empty() {
+                        String::from("everybody")
+                    } else {
+                        sharing.join(", ")
+                    };
+
+                    let paid_by: Vec<String> = ledger
+                        .payers
+                        .iter()
+                        .filter(|x| x.expense_id == exp.id)
+                        .map(|x| format!("{} ({})", member_name(&people, x.user_id), x.amount))
+                        .collect();
+                    let paid_by = if paid_by.is_empty() {
+                        member_name(&people, exp.payer)
+                    } else {
+                        paid_by.join(", ")
+                    };
+
+                    let formatted_string = format!(
+                        "{}: {} spent {} {} with note: {} ({:?} split between {})\n",
+                        exp.created_at.format("%Y-%m-%d"),
+                        paid_by,
+                        exp.amount,
+                        exp.currency,
+                        exp.note,
+                        exp.split_mode,
+                        sharing
+                    );
+                    text.push_str(&formatted_string);
+
+                    if exp.currency != ledger.currency {
+                        text.push_str(&format!(
+                            "   1 {} = {} {}\n",
+                            exp.currency, exp.exchange_rate, ledger.currency
+                        ));
+                    }
+
+                    if exp.created_by != exp.payer {
+                        text.push_str(&format!(
+                            "   logged by {}\n",
+                            member_name(&people, exp.created_by)
+                        ));
+                    }
+                }
+
+                if !ledger.payments.is_empty() {
+                    text.push_str("\n --- \n Settle-up payments:\n");
+
+                    for payment in ledger.payments.iter() {
+                        let formatted_string = format!(
+                            "{}: {} paid {} {} back to {}\n",
+                            payment.created_at.format("%Y-%m-%d"),
+                            member_name(&people, payment.from_user),
+                            payment.amount,
+                            payment.currency,
+                            member_name(&people, payment.to_user)
+                        );
+                        text.push_str(&formatted_string);
+                    }
+                }
+
+                if transactions.is_empty() {
+                    bot.send_message(msg.chat.id, text).await?;
+                } else {
+                    bot.send_message(msg.chat.id, text)
+                        .reply_markup(settle_all_keyboard(group_id, &transactions))
+                        .await?;
+                }
+            } else {
+                bot.send_message(msg.chat.id, "There are no expenses yet in this group")
+                    .await?;
+            }
+
+            dialogue.update(ChatState::Start).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Group, amount, currency and exchange rate of the expense being added
+type NoteData = (i64, rust_decimal::Decimal, String, rust_decimal::Decimal);
+
+async fn receive_note(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    data: NoteData,
+) -> HandlerResult {
+    if let Some(note) = msg.text() {
+        ask_payer(&bot, &dialogue, &msg, data, note.to_owned()).await?;
+    }
+
+    Ok(())
+}
+
+/// Asks for the note of the expense, unless it's known already
+async fn ask_note(
+    bot: &Bot,
+    dialogue: &MyDialogue,
+    msg: &Message,
+    data: NoteData,
+    note: Option<String>,
+) -> HandlerResult {
+    if let Some(note) = note {
+        return ask_payer(bot, dialogue, msg, data, note).await;
+    }
+
+    let (group_id, amount, currency, exchange_rate) = data;
+    bot.send_message(msg.chat.id, "Provide some note:")
+        .prompt(msg)
+        .await?;
+    dialogue
+        .update(ChatState::ReceiveNote {
+            group_id,
+            amount,
+            currency,
+            exchange_rate,
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn ask_payer(
+    bot: &Bot,
+    dialogue: &MyDialogue,
+    msg: &Message,
+    data: NoteData,
+    note: String,
+) -> HandlerResult {
+    let (group_id, amount, currency, exchange_rate) = data;
+    let ctl = Controller::from_msg(bot, msg).await?;
+    let members = ctl.get_users_in_group(group_id).await?;
+
+    let text = format!(
+        "Who paid? Send `me`, the number of the member or, if several people paid, the amount of every payer, one per line, e.g. `1 30`:\n {}",
+        users_to_pretty(&members)
+    );
+    // Members are sent as `#<id>`, like `members_keyboard` does, which `parse_payers` resolves
+    let payers = std::iter::once((String::from("me"), String::from("me")))
+        .chain(members.iter().map(|x| (x.mention(), format!("#{}", x.id))));
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(keyboard(payers))
+        .await?;
+
+    dialogue
+        .update(ChatState::ReceivePayer {
+            group_id,
+            amount,
+            currency,
+            exchange_rate,
+            note,
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Parses either a single payer, who paid the whole amount, or a `<member> <amount>` pair per line
+fn parse_payers(
+    text: &str,
+    members: &[user::Model],
+    author: i64,
+    amount: Decimal,
+) -> Option<Vec<(i64, Decimal)>> {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case("me") {
+        return Some(vec![(author, amount)]);
+    }
+
+    if let Some(member) = find_member(text, members) {
+        return Some(vec![(member.id, amount)]);
+    }
+
+    parse_member_values(text, members)
+}
+
+async fn receive_payer(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    data: (
+        i64,
+        rust_decimal::Decimal,
+        String,
+        rust_decimal::Decimal,
+        String,
+    ),
+) -> HandlerResult {
+    let (group_id, amount, currency, exchange_rate, note) = data;
+    if let Some(text) = msg.text() {
+        let ctl = Controller::from_msg(&bot, &msg).await?;
+        let author = get_author(&ctl, &msg).await?;
+        let members = ctl.get_users_in_group(group_id).await?;
+
+        let Some(payers) = parse_payers(text, &members, author.id, amount) else {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send `me`, the number of the member from the list or amounts of payers:",
+            )
+            .prompt(&msg)
+            .await?;
+            return Ok(());
+        };
+
+        if let Err(err) = settlement::validate_payers(amount, &payers) {
+            let text = format!("{}. Please, try again:", err);
+            bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+            return Ok(());
+        }
+
+        bot.send_message(
+            msg.chat.id,
+            "How to split the expense? Send one of: `equal`, `exact`, `percent` or `shares`:",
+        )
+        .reply_markup(options_keyboard(SPLIT_MODES))
+        .await?;
+
+        dialogue
+            .update(ChatState::ReceiveSplitMode {
+                group_id,
+                amount,
+                currency,
+                exchange_rate,
+                note,
+                payers,
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn parse_split_mode(text: &str) -> Option<SplitMode> {
+    match text.trim().to_lowercase().as_str() {
+        "equal" => Some(SplitMode::Equal),
+        "exact" => Some(SplitMode::Exact),
+        "percent" => Some(SplitMode::Percent),
+        "shares" => Some(SplitMode::Shares),
+        _ => None,
+    }
+}
+
+async fn receive_split_mode(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    data: (
+        i64,
+        rust_decimal::Decimal,
+        String,
+        rust_decimal::Decimal,
+        String,
+        Vec<(i64, Decimal)>,
+    ),
+) -> HandlerResult {
+    let (group_id, amount, currency, exchange_rate, note, payers) = data;
+    if let Some(text) = msg.text() {
+        if let Some(split_mode) = parse_split_mode(text) {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            let members = ctl.get_users_in_group(group_id).await?;
+
+            let text = format!(
+                "{}:\n {}",
+                participants_hint(split_mode),
+                users_to_pretty(&members)
+            );
+            if split_mode == SplitMode::Equal {
+                bot.send_message(msg.chat.id, text)
+                    .reply_markup(options_keyboard(&["all"]))
+                    .await?;
+            } else {
+                bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+            }
+
+            dialogue
+                .update(ChatState::ReceiveParticipants {
+                    group_id,
+                    amount,
+                    currency,
+                    exchange_rate,
+                    note,
+                    payers,
+                    split_mode,
+                })
+                .await?;
+        } else {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send one of: `equal`, `exact`, `percent` or `shares`:",
+            )
+            .prompt(&msg)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn participants_hint(split_mode: SplitMode) -> &'static str {
+    match split_mode {
+        SplitMode::Equal => {
+            "Who shared this expense? Send `all` or the numbers of participants separated by spaces"
+        }
+        SplitMode::Exact => "Send the amount of every participant, one per line, e.g. `1 12.50`",
+        SplitMode::Percent => "Send the percent of every participant, one per line, e.g. `1 40`",
+        SplitMode::Shares => "Send the shares of every participant, one per line, e.g. `1 2`",
+    }
+}
+
+fn users_to_pretty(users: &[user::Model]) -> String {
+    users
+        .iter()
+        .enumerate()
+        .map(|(index, model)| {
+            if model.is_placeholder() {
+                format!("{} — {} (not on Telegram)\n", index + 1, model.mention())
+            } else {
+                format!("{} — {}\n", index + 1, model.mention())
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Finds a member either by its number in the list, by `#<id>` as `members_keyboard` sends it or by
+/// how it's shown, i.e. its username or, for placeholders, its name. Only those in `members` are
+/// found, so a tap on a member who has left since is refused
+fn find_member<'a>(token: &str, members: &'a [user::Model]) -> Option<&'a user::Model> {
+    if let Some(user_id) = token.strip_prefix('#') {
+        let user_id = user_id.parse::<i64>().ok()?;
+        return members.iter().find(|x| x.id == user_id);
+    }
+
+    match token.parse::<usize>() {
+        Ok(index) => members.get(index.checked_sub(1)?),
+        Err(_) => members
+            .iter()
+            .find(|x| x.mention().eq_ignore_ascii_case(token)),
+    }
+}
+
+/// How a user is shown in messages, falls back to the id for users that aren't in the list
+fn member_name(members: &[user::Model], user_id: i64) -> String {
+    members
+        .iter()
+        .find(|x| x.id == user_id)
+        .map(|x| x.mention())
+        .unwrap_or_else(|| format!("#{}", user_id))
+}
+
+/// Parses a `<member> <value>` pair per line, every member may appear only once
+fn parse_member_values(text: &str, members: &[user::Model]) -> Option<Vec<(i64, Decimal)>> {
+    let mut values: Vec<(i64, Decimal)> = Vec::new();
+
+    for line in text.split([',', '\n']) {
+        let mut tokens = line.split_whitespace();
+        let (Some(member), Some(value), None) = (tokens.next(), tokens.next(), tokens.next())
+        else {
+            if line.trim().is_empty() {
+                continue;
+            }
+            return None;
+        };
+
+        let member = find_member(member, members)?;
+        let value = value.parse::<Decimal>().ok()?;
+        if values.iter().any(|x| x.0 == member.id) {
+            return None;
+        }
+        values.push((member.id, value));
+    }
+
+    if values.is_empty() {
+        None
+    } else {
+        Some(values)
+    }
+}
+
+/// Parses participants of an expense and their shares.
+/// For equal split it's either `all`, which stands for every current member, or a list of members.
+/// Other modes expect a `<member> <share>` pair per line
+fn parse_participants(
+    text: &str,
+    members: &[user::Model],
+    split_mode: SplitMode,
+) -> Option<Vec<(i64, Option<Decimal>)>> {
+    let mut participants: Vec<(i64, Option<Decimal>)> = Vec::new();
+
+    if split_mode == SplitMode::Equal {
+        if text.trim().eq_ignore_ascii_case("all") {
+            return Some(members.iter().map(|x| (x.id, None)).collect());
+        }
+
+        for token in text.split(|c: char| c == ',' || c.is_whitespace()) {
+            if token.is_empty() {
+                continue;
+            }
+
+            let member = find_member(token, members)?;
+            if !participants.iter().any(|x| x.0 == member.id) {
+                participants.push((member.id, None));
+            }
+        }
+    } else {
+        participants = parse_member_values(text, members)?
+            .into_iter()
+            .map(|(user_id, share)| (user_id, Some(share)))
+            .collect();
+    }
+
+    if participants.is_empty() {
+        None
+    } else {
+        Some(participants)
+    }
+}
+
+/// Group, amount, currency, exchange rate, note, payers and split mode of the expense being added
+type ParticipantsData = (
+    i64,
+    rust_decimal::Decimal,
+    String,
+    rust_decimal::Decimal,
+    String,
+    Vec<(i64, Decimal)>,
+    SplitMode,
+);
+
+async fn receive_participants(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    data: ParticipantsData,
+) -> HandlerResult {
+    let (group_id, amount, currency, exchange_rate, note, payers, split_mode) = data;
+    if let Some(text) = msg.text() {
+        let ctl = Controller::from_msg(&bot, &msg).await?;
+        let author = get_author(&ctl, &msg).await?;
+        let members = ctl.get_users_in_group(group_id).await?;
+
+        let Some(participants) = parse_participants(text, &members, split_mode) else {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send participants in the requested format, using numbers from the list:",
+            )
+            .prompt(&msg)
+            .await?;
+            return Ok(());
+        };
+
+        if let Err(err) = settlement::validate_split(split_mode, amount, &participants) {
+            let text = format!("{}. Please, try again:", err);
+            bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+            return Ok(());
+        }
+
+        // The main payer is the one who paid the most. Contributions are stored only if there are several payers
+        let payer = payers
+            .iter()
+            .max_by_key(|x| x.1)
+            .map(|x| x.0)
+            .unwrap_or(author.id);
+        let payers = if payers.len() > 1 { payers } else { Vec::new() };
+
+        ctl.add_expense(&NewExpense {
+            group_id,
+            payer,
+            payers,
+            created_by: author.id,
+            amount,
+            currency,
+            exchange_rate,
+            note,
+            split_mode,
+            participants,
+        })
+        .await?;
+        bot.send_message(msg.chat.id, "The expense has been added")
+            .await?;
+
+        dialogue.update(ChatState::Start).await?;
+    }
+
+    Ok(())
+}
+
+async fn receive_amount_spent(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    if let Some(text) = msg.text() {
+        if let Some((amount, currency)) = parse_amount(text) {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            let group = ctl.authorize_group(group_id).await?;
+
+            let draft = ExpenseDraft {
+                amount,
+                currency,
+                note: None,
+            };
+            accept_amount(&bot, &dialogue, &msg, group, draft).await?;
+        } else {
+            bot.send_message(
+                msg.chat.id,
+                "Please, provide some decimal value, optionally followed by a currency code:",
+            )
+            .prompt(&msg)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Goes on with an expense once its amount is known, asking only for what the draft lacks.
+/// If the amount isn't right, the next message is taken as the amount again
+async fn accept_amount(
+    bot: &Bot,
+    dialogue: &MyDialogue,
+    msg: &Message,
+    group: group::Model,
+    draft: ExpenseDraft,
+) -> HandlerResult {
+    let ExpenseDraft {
+        amount,
+        currency,
+        note,
+    } = draft;
+    let group_id = group.id;
+
+    if amount <= Decimal::ZERO {
+        bot.send_message(msg.chat.id, "Please, provide some positive amount:")
+            .prompt(msg)
+            .await?;
+        dialogue
+            .update(ChatState::RecieveAmountSpent { group_id })
+            .await?;
+        return Ok(());
+    }
+
+    if let Err(err) = settlement::validate_amount(amount) {
+        bot.send_message(
+            msg.chat.id,
+            format!("{}, please send the amount again:", err),
+        )
+        .prompt(msg)
+        .await?;
+        dialogue
+            .update(ChatState::RecieveAmountSpent { group_id })
+            .await?;
+        return Ok(());
+    }
+
+    let unit_currency = currency.as_deref().unwrap_or(&group.currency);
+    if let Err(err) = settlement::validate_minor_units(amount, unit_currency) {
+        let text = format!("{}, please send the amount again:", err);
+        bot.send_message(msg.chat.id, text).prompt(msg).await?;
+        dialogue
+            .update(ChatState::RecieveAmountSpent { group_id })
+            .await?;
+        return Ok(());
+    }
+
+    // Expenses lock in the rate of the day they were made
+    let ctl = Controller::from_msg(bot, msg).await?;
+    let today = chrono::Utc::now().date_naive();
+    let rate = match currency {
+        Some(ref currency) if *currency != group.currency => Some(
+            ctl.find_exchange_rate(today, currency, &group.currency)
+                .await?
+                .filter(|x| settlement::validate_rate(x.rate).is_ok()),
+        ),
+        _ => None,
+    };
+
+    let (currency, exchange_rate) = match (currency, rate) {
+        (Some(currency), Some(Some(rate))) => {
+            let text = format!(
+                "Using the rate of {}: 1 {} = {} {}",
+                rate.date, currency, rate.rate, group.currency
+            );
+            bot.send_message(msg.chat.id, text).await?;
+
+            (currency, rate.rate)
+        }
+        (Some(currency), Some(None)) => {
+            let text = format!(
+                "There is no exchange rate for {} yet. How much {} was 1 {} worth?",
+                currency, group.currency, currency
+            );
+            bot.send_message(msg.chat.id, text).prompt(msg).await?;
+
+            dialogue
+                .update(ChatState::ReceiveExchangeRate {
+                    group_id,
+                    amount,
+                    currency,
+                    note,
+                })
+                .await?;
+            return Ok(());
+        }
+        _ => (group.currency, Decimal::ONE),
+    };
+
+    ask_note(
+        bot,
+        dialogue,
+        msg,
+        (group_id, amount, currency, exchange_rate),
+        note,
+    )
+    .await
+}
+
+async fn set_rate(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+
+    if ctl.is_admin() {
+        bot.send_message(
+            msg.chat.id,
+            "Send the rate as `<from> <to> <rate> [date]`, e.g. `USD EUR 0.92` for today or `USD EUR 0.92 2024-03-15`:",
+        )
+        .prompt(&msg).await?;
+        dialogue.update(ChatState::ReceiveRateEntry).await?;
+    } else {
+        bot.send_message(msg.chat.id, "Only admins of the bot can set exchange rates")
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Parses `<from> <to> <rate> [date]`, where the date defaults to today
+fn parse_rate_entry(text: &str) -> Option<(chrono::NaiveDate, String, String, Decimal)> {
+    let mut tokens = text.split_whitespace();
+    let from = parse_currency(tokens.next()?)?;
+    let to = parse_currency(tokens.next()?)?;
+    let rate = tokens.next()?.parse::<Decimal>().ok()?;
+    let date = match tokens.next() {
+        Some(date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?,
+        None => chrono::Utc::now().date_naive(),
+    };
+
+    if tokens.next().is_some() || from == to || settlement::validate_rate(rate).is_err() {
+        return None;
+    }
+
+    Some((date, from, to, rate))
+}
+
+async fn receive_rate_entry(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
+    if let Some(text) = msg.text() {
+        if let Some((date, from, to, rate)) = parse_rate_entry(text) {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            ctl.set_exchange_rate(date, &from, &to, rate).await?;
+
+            let text = format!("Recorded that 1 {} = {} {} on {}", from, rate, to, date);
+            bot.send_message(msg.chat.id, text).await?;
+
+            dialogue.update(ChatState::Start).await?;
+        } else {
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Please, send two different currency codes, a rate between {} and {} and optionally a date, e.g. `USD EUR 0.92`:",
+                    settlement::MIN_RATE,
+                    settlement::MAX_RATE
+                ),
+            )
+            .prompt(&msg).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses an amount optionally followed by a currency code, e.g. `12.50` or `12.50 EUR`
+fn parse_amount(text: &str) -> Option<(Decimal, Option<String>)> {
+    let mut tokens = text.split_whitespace();
+    let amount = tokens.next()?.parse::<Decimal>().ok()?;
+
+    match (tokens.next(), tokens.next()) {
+        (None, _) => Some((amount, None)),
+        (Some(currency), None) => Some((amount, Some(parse_currency(currency)?))),
+        _ => None,
+    }
+}
+
+async fn receive_exchange_rate(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    data: (i64, rust_decimal::Decimal, String, Option<String>),
+) -> HandlerResult {
+    let (group_id, amount, currency, note) = data;
+    if let Some(exchange_rate) = msg.text() {
+        if let Ok(exchange_rate) = exchange_rate.trim().parse::<Decimal>() {
+            if let Err(err) = settlement::validate_rate(exchange_rate) {
+                bot.send_message(msg.chat.id, format!("{}, please send the rate again:", err))
+                    .prompt(&msg)
+                    .await?;
+            } else {
+                ask_note(
+                    &bot,
+                    &dialogue,
+                    &msg,
+                    (group_id, amount, currency, exchange_rate),
+                    note,
+                )
+                .await?;
+            }
+        } else {
+            bot.send_message(msg.chat.id, "Please, provide some decimal value:")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_user_name(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    if let Some(nickname) = msg.text() {
+        add_member(&bot, &dialogue, &msg, group_id, nickname).await?;
+    }
+
+    Ok(())
+}
+
+/// Adds either a Telegram user by @username or somebody who isn't on Telegram by name.
+/// If that's not possible, the next message is taken as another name
+async fn add_member(
+    bot: &Bot,
+    dialogue: &MyDialogue,
+    msg: &Message,
+    group_id: i64,
+    nickname: &str,
+) -> HandlerResult {
+    dialogue
+        .update(ChatState::ReceiveUsername { group_id })
+        .await?;
+
+    let nickname = nickname.trim();
+    if nickname.is_empty() || nickname == "@" {
+        bot.send_message(
+            msg.chat.id,
+            "Please, provide a username, starting from `@`, or a name:",
+        )
+        .prompt(msg)
+        .await?;
+    } else if nickname.starts_with('@') {
+        let ctl = Controller::from_msg(bot, msg).await?;
+        let user = ctl.get_or_add_user(nickname).await?;
+        ctl.add_user_to_a_group(user.id, group_id).await?;
+
+        let text = format!("User {} has been successfully added to a group", nickname);
+        bot.send_message(msg.chat.id, text).await?;
+
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        // Somebody who isn't on Telegram, e.g. a kid. Names must be unique within the group
+        let ctl = Controller::from_msg(bot, msg).await?;
+        let members = ctl.get_users_in_group(group_id).await?;
+
+        if find_member(nickname, &members).is_some() {
+            let text = format!(
+                "There is already a member called {}, pick another name:",
+                nickname
+            );
+            bot.send_message(msg.chat.id, text).prompt(msg).await?;
+        } else {
+            ctl.add_placeholder_to_a_group(nickname, group_id).await?;
+
+            let text = format!(
+                "{} has been added to a group. Once they are on Telegram, send /mergemember to hand their expenses over",
+                nickname
+            );
+            bot.send_message(msg.chat.id, text).await?;
+
+            dialogue.update(ChatState::Start).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_group_id_for_add_member(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    name: Option<String>,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            ctl.authorize_change(group_id).await?;
+
+            if let Some(name) = name {
+                add_member(&bot, &dialogue, &msg, group_id, &name).await?;
+            } else {
+                bot.send_message(
+                    msg.chat.id,
+                    "Provide @username of that user or a name of somebody who isn't on Telegram: ",
+                )
+                .prompt(&msg)
+                .await?;
+                dialogue
+                    .update(ChatState::ReceiveUsername { group_id })
+                    .await?;
+            }
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn invite(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_invite(bot, dialogue, answer).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group you'd like to invite people to:\n {}",
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue.update(ChatState::ReceiveGroupIdForInvite).await?;
+    }
+
+    Ok(())
+}
+
+async fn receive_group_id_for_invite(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            ctl.authorize_change(group_id).await?;
+
+            bot.send_message(
+                msg.chat.id,
+                "For how many days should the link work? Send a number or `never` to keep it working:",
+            )
+            .reply_markup(options_keyboard(&["1", "7", "30", "never"]))
+            .await?;
+            dialogue
+                .update(ChatState::ReceiveInviteExpiry { group_id })
+                .await?;
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A positive number, or `None` for the word that means no limit
+fn parse_limit<T: std::str::FromStr + Default + PartialOrd>(
+    text: &str,
+    unlimited: &str,
+) -> Option<Option<T>> {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case(unlimited) {
+        return Some(None);
+    }
+
+    text.parse::<T>()
+        .ok()
+        .filter(|x| *x > T::default())
+        .map(Some)
+}
+
+async fn receive_invite_expiry(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    if let Some(text) = msg.text() {
+        let days =
+            parse_limit::<u32>(text, "never").filter(|x| x.is_none_or(|x| x <= MAX_INVITE_DAYS));
+        if let Some(days) = days {
+            bot.send_message(
+                msg.chat.id,
+                "How many people may join by the link? Send a number or `unlimited`:",
+            )
+            .reply_markup(options_keyboard(&["1", "5", "20", "unlimited"]))
+            .await?;
+            dialogue
+                .update(ChatState::ReceiveInviteUses { group_id, days })
+                .await?;
+        } else {
+            let text = format!(
+                "Please, send a number of days up to {} or `never`:",
+                MAX_INVITE_DAYS
+            );
+            bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_invite_uses(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    data: (i64, Option<u32>),
+) -> HandlerResult {
+    let (group_id, days) = data;
+    if let Some(text) = msg.text() {
+        if let Some(max_uses) = parse_limit::<i32>(text, "unlimited") {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            let valid_for = days.map(|x| chrono::Duration::days(x.into()));
+            let invite = match ctl.create_invite(group_id, valid_for, max_uses).await {
+                Ok(invite) => invite,
+                Err(err) => match err.downcast_ref::<InviteError>() {
+                    Some(err) => {
+                        bot.send_message(msg.chat.id, format!("😔{}😔", err))
+                            .await?;
+                        dialogue.update(ChatState::Start).await?;
+                        return Ok(());
+                    }
+                    None => return Err(err.into()),
+                },
+            };
+            let me = bot.get_me().await?;
+
+            let mut text = format!(
+                "Whoever opens this link joins the group:\nhttps://t.me/{}?start={}",
+                me.username(),
+                invite.token
+            );
+            if let Some(expires_at) = invite.expires_at {
+                text.push_str(&format!(
+                    "\nIt works until {}",
+                    expires_at.format("%Y-%m-%d %H:%M UTC")
+                ));
+            }
+            if let Some(max_uses) = invite.max_uses {
+                text.push_str(&format!("\nIt lets in {} people at most", max_uses));
+            }
+            bot.send_message(msg.chat.id, text).await?;
+            dialogue.update(ChatState::Start).await?;
+        } else {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send a positive number or `unlimited`:",
+            )
+            .prompt(&msg)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn merge_member(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_merge_member(bot, dialogue, answer).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group where the member who isn't on Telegram is:\n {}",
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue
+            .update(ChatState::ReceiveGroupIdForMergeMember)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn receive_group_id_for_merge_member(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+
+            ctl.authorize_admin(group_id).await?;
+            let placeholders: Vec<user::Model> = ctl
+                .get_users_in_group(group_id)
+                .await?
+                .into_iter()
+                .filter(|x| x.is_placeholder())
+                .collect();
+
+            if placeholders.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "Everybody in this group is already on Telegram",
+                )
+                .await?;
+                dialogue.update(ChatState::Start).await?;
+            } else {
+                let text = format!(
+                    "Who has joined Telegram? Send the number of the member:\n {}",
+                    users_to_pretty(&placeholders)
+                );
+                bot.send_message(msg.chat.id, text)
+                    .reply_markup(members_keyboard(&placeholders))
+                    .await?;
+                dialogue
+                    .update(ChatState::ReceivePlaceholder { group_id })
+                    .await?;
+            }
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_placeholder(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    if let Some(text) = msg.text() {
+        let ctl = Controller::from_msg(&bot, &msg).await?;
+        let placeholders: Vec<user::Model> = ctl
+            .get_users_in_group(group_id)
+            .await?
+            .into_iter()
+            .filter(|x| x.is_placeholder())
+            .collect();
+
+        if let Some(placeholder) = find_member(text.trim(), &placeholders) {
+            let text = format!(
+                "Provide @username of {} on Telegram: ",
+                placeholder.mention()
+            );
+            bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+            dialogue
+                .update(ChatState::ReceiveMergeTarget {
+                    group_id,
+                    placeholder_id: placeholder.id,
+                })
+                .await?;
+        } else {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send the number of the member from the list:",
+            )
+            .prompt(&msg)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_merge_target(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    data: (i64, i64),
+) -> HandlerResult {
+    let (group_id, placeholder_id) = data;
+    if let Some(nickname) = msg.text() {
+        let nickname = nickname.trim();
+        if nickname.starts_with('@') && nickname.len() > 1 {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            let author = get_author(&ctl, &msg).await?;
+            // The placeholder goes either to the author or to a member who is on Telegram already
+            let members = ctl.get_users_in_group(group_id).await?;
+            let user = match author.mention().eq_ignore_ascii_case(nickname) {
+                true => Some(&author),
+                false => find_member(nickname, &members).filter(|x| !x.is_placeholder()),
+            };
+            let Some(user) = user else {
+                bot.send_message(
+                    msg.chat.id,
+                    "Please, provide your own @username or the one of a member of the group, /addmembertogroup adds others first:",
+                )
+                .prompt(&msg).await?;
+                return Ok(());
+            };
+            ctl.merge_placeholder(group_id, placeholder_id, user.id)
+                .await?;
+
+            let text = format!(
+                "Expenses and payments of the placeholder now belong to {}",
+                nickname
+            );
+            bot.send_message(msg.chat.id, text).await?;
+
+            dialogue.update(ChatState::Start).await?;
+        } else {
+            bot.send_message(
+                msg.chat.id,
+                "Please, provide a username, starting from `@`:",
+            )
+            .prompt(&msg)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Registers the author of the message or refreshes their @username and name
+async fn get_author(
+    ctl: &Controller<'_>,
+    msg: &Message,
+) -> Result<user::Model, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(user) = msg.from() {
+        let username = user.username.as_ref().map(|x| format!("@{}", x));
+        Ok(ctl
+            .sync_user(username.as_deref(), &user.full_name())
+            .await?)
+    } else {
+        Err("😔Sorry, I can't get info about you😔".into())
+    }
+}
+
+async fn cancel(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    bot.send_message(msg.chat.id, "Canceled whatever you did")
+        .await?;
+    dialogue.exit().await?;
+    Ok(())
+}
+
+async fn promote(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    change_role(bot, msg, dialogue, Role::Admin).await
+}
+
+async fn demote(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    change_role(bot, msg, dialogue, Role::Member).await
+}
+
+/// Starts giving `role` to a member of a group
+async fn change_role(bot: Bot, msg: Message, dialogue: MyDialogue, role: Role) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_role_change(bot, dialogue, answer, role).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group where you'd like to {} somebody:\n {}",
+            if role.is_admin() { "promote" } else { "demote" },
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue
+            .update(ChatState::ReceiveGroupIdForRoleChange { role })
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Members of a group who can be given `role`. Placeholders can't manage anything, so they are
+/// never promoted
+async fn role_candidates(
+    ctl: &Controller<'_>,
+    group_id: i64,
+    role: Role,
+) -> anyhow::Result<Vec<user::Model>> {
+    let members = if role.is_admin() {
+        ctl.get_members_with_role(group_id, Role::Member).await?
+    } else {
+        ctl.get_members_with_role(group_id, Role::Admin).await?
+    };
+
+    Ok(members
+        .into_iter()
+        .filter(|x| !x.is_placeholder())
+        .collect())
+}
+
+async fn receive_group_id_for_role_change(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    role: Role,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            ctl.authorize_admin(group_id).await?;
+
+            let candidates = role_candidates(&ctl, group_id, role).await?;
+            if candidates.is_empty() {
+                let text = if role.is_admin() {
+                    "Everybody in this group who is on Telegram is an admin already"
+                } else {
+                    "The group has no admins besides its owner"
+                };
+                bot.send_message(msg.chat.id, text).await?;
+                dialogue.update(ChatState::Start).await?;
+            } else {
+                let text = format!(
+                    "Who should {}? Send the number of the member:\n {}",
+                    if role.is_admin() {
+                        "become an admin"
+                    } else {
+                        "stop being an admin"
+                    },
+                    users_to_pretty(&candidates)
+                );
+                bot.send_message(msg.chat.id, text)
+                    .reply_markup(members_keyboard(&candidates))
+                    .await?;
+                dialogue
+                    .update(ChatState::ReceiveMemberForRoleChange { group_id, role })
+                    .await?;
+            }
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_member_for_role_change(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    data: (i64, Role),
+) -> HandlerResult {
+    let (group_id, role) = data;
+    if let Some(text) = msg.text() {
+        let ctl = Controller::from_msg(&bot, &msg).await?;
+        let candidates = role_candidates(&ctl, group_id, role).await?;
+
+        if let Some(member) = find_member(text.trim(), &candidates) {
+            ctl.set_member_role(group_id, member.id, role).await?;
+
+            let text = if role.is_admin() {
+                format!("{} is an admin of the group now", member.mention())
+            } else {
+                format!("{} is a regular member of the group now", member.mention())
+            };
+            bot.send_message(msg.chat.id, text).await?;
+            dialogue.update(ChatState::Start).await?;
+        } else {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send the number of the member from the list:",
+            )
+            .prompt(&msg)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn leave_group(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_leave(bot, dialogue, answer).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!("Choose id of the group you'd like to leave:\n {}", groups);
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue.update(ChatState::ReceiveGroupIdForLeave).await?;
+    }
+
+    Ok(())
+}
+
+async fn receive_group_id_for_leave(bot: Bot, dialogue: MyDialogue, msg: Message) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            let author = get_author(&ctl, &msg).await?;
+
+            ctl.authorize_group(group_id).await?;
+            ask_removal(&bot, &dialogue, &msg, &ctl, group_id, &author).await?;
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn remove_member(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_remove_member(bot, dialogue, answer).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group you'd like to remove a member from:\n {}",
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue
+            .update(ChatState::ReceiveGroupIdForRemoveMember)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Members an admin can remove: everybody but the owner and the admin, who'd rather leave
+async fn removable_members(
+    ctl: &Controller<'_>,
+    group_id: i64,
+    author: &user::Model,
+) -> anyhow::Result<Vec<user::Model>> {
+    let owners = ctl.get_members_with_role(group_id, Role::Owner).await?;
+
+    Ok(ctl
+        .get_users_in_group(group_id)
+        .await?
+        .into_iter()
+        .filter(|x| x.id != author.id && !owners.iter().any(|owner| owner.id == x.id))
+        .collect())
+}
+
+async fn receive_group_id_for_remove_member(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            let author = get_author(&ctl, &msg).await?;
+            ctl.authorize_admin(group_id).await?;
+
+            let members = removable_members(&ctl, group_id, &author).await?;
+            if members.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "There is nobody you can remove from this group",
+                )
+                .await?;
+                dialogue.update(ChatState::Start).await?;
+            } else {
+                let text = format!(
+                    "Who should be removed? Send the number of the member:\n {}",
+                    users_to_pretty(&members)
+                );
+                bot.send_message(msg.chat.id, text)
+                    .reply_markup(members_keyboard(&members))
+                    .await?;
+                dialogue
+                    .update(ChatState::ReceiveMemberToRemove { group_id })
+                    .await?;
+            }
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_member_to_remove(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    if let Some(text) = msg.text() {
+        let ctl = Controller::from_msg(&bot, &msg).await?;
+        let author = get_author(&ctl, &msg).await?;
+        let members = removable_members(&ctl, group_id, &author).await?;
+
+        if let Some(member) = find_member(text.trim(), &members) {
+            ask_removal(&bot, &dialogue, &msg, &ctl, group_id, member).await?;
+        } else {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send the number of the member from the list:",
+            )
+            .prompt(&msg)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Takes a settled up member out of the group right away. Otherwise shows what they still owe or
+/// are owed and asks whether to go on anyway
+async fn ask_removal(
+    bot: &Bot,
+    dialogue: &MyDialogue,
+    msg: &Message,
+    ctl: &Controller<'_>,
+    group_id: i64,
+    member: &user::Model,
+) -> HandlerResult {
+    let ledger = ctl.get_ledger(group_id).await?;
+    let debts: Vec<settlement::Transfer> = settlement::debts(&ledger)?
+        .into_iter()
+        .filter(|x| x.from == member.id || x.to == member.id)
+        .collect();
+    if debts.is_empty() {
+        return finish_removal(bot, dialogue, msg, ctl, group_id, member, false).await;
+    }
+
+    let author = get_author(ctl, msg).await?;
+    let leaving = author.id == member.id;
+    let people: Vec<user::Model> = ledger
+        .members
+        .iter()
+        .chain(ledger.former_members.iter())
+        .cloned()
+        .collect();
+
+    let mut text = if leaving {
+        String::from("You aren't settled up yet:\n")
+    } else {
+        format!("{} isn't settled up yet:\n", member.mention())
+    };
+    for transfer in debts.iter() {
+        text.push_str(&format!(
+            "😑{} owes {} {} to {}😑\n",
+            member_name(&people, transfer.from),
+            transfer.amount,
+            ledger.currency,
+            member_name(&people, transfer.to)
+        ));
+    }
+    text.push_str(if leaving {
+        "Your expenses and payments stay in the group either way. Send `yes` to leave anyway or `no` to stay:"
+    } else {
+        "Their expenses and payments stay in the group either way. Send `yes` to remove them anyway or `no` to keep them:"
+    });
+
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(options_keyboard(&["yes", "no"]))
+        .await?;
+    dialogue
+        .update(ChatState::ReceiveRemovalConfirmation {
+            group_id,
+            user_id: member.id,
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn receive_removal_confirmation(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    data: (i64, i64),
+) -> HandlerResult {
+    let (group_id, user_id) = data;
+    if let Some(text) = msg.text() {
+        match text.trim().to_lowercase().as_str() {
+            "yes" => {
+                let ctl = Controller::from_msg(&bot, &msg).await?;
+                let member = ctl
+                    .get_user_by_id(user_id)
+                    .await?
+                    .ok_or(anyhow::anyhow!("Inexistent user id"))?;
+                finish_removal(&bot, &dialogue, &msg, &ctl, group_id, &member, true).await?;
+            }
+            "no" => {
+                bot.send_message(msg.chat.id, "Nothing has changed").await?;
+                dialogue.update(ChatState::Start).await?;
+            }
+            _ => {
+                bot.send_message(msg.chat.id, "Please, send `yes` or `no`:")
+                    .prompt(&msg)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn finish_removal(
+    bot: &Bot,
+    dialogue: &MyDialogue,
+    msg: &Message,
+    ctl: &Controller<'_>,
+    group_id: i64,
+    member: &user::Model,
+    force: bool,
+) -> HandlerResult {
+    let author = get_author(ctl, msg).await?;
+
+    let text = match ctl.remove_member(group_id, member.id, force).await {
+        Ok(()) if author.id == member.id => String::from("You've left the group"),
+        Ok(()) => format!("{} has been removed from the group", member.mention()),
+        Err(err) => match err.downcast_ref::<RemovalError>() {
+            Some(err) => format!("😔{}😔", err),
+            None => return Err(err.into()),
+        },
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    dialogue.update(ChatState::Start).await?;
+
+    Ok(())
+}
+
+async fn list_expenses_in_group(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_expenses_list(bot, dialogue, answer).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "You are not a member of any group, yet. You can create one with /creategroup",
+        )
+        .await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!("Good, choose id of one of your groups:\n {}", groups);
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+    }
+
+    dialogue
+        .update(ChatState::ReceiveGroupIdForExpensesList)
+        .await?;
+
+    Ok(())
+}
+
+fn expenses_to_pretty(expenses: &[expense::Model]) -> String {
+    expenses
+        .iter()
+        .map(|model| {
+            format!(
+                "{} — {} — {} {} — `{}`\n",
+                model.id,
+                model.created_at.format("%Y-%m-%d"),
+                model.amount,
+                model.currency,
+                model.note
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+async fn edit_expense(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_edit_expense(bot, dialogue, answer).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group where you'd like to edit the expense:\n {}",
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue
+            .update(ChatState::ReceiveGroupIdForEditExpense)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Lists expenses the author can modify and returns whether there are any
+async fn send_modifiable_expenses(
+    bot: &Bot,
+    msg: &Message,
+    ctl: &Controller<'_>,
+    group_id: i64,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let author = get_author(ctl, msg).await?;
+    let expenses = ctl.get_modifiable_expenses(author.id, group_id).await?;
+
+    if expenses.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "There are no expenses you can change in this group",
+        )
+        .await?;
+        Ok(false)
+    } else {
+        let text = format!(
+            "Choose id of the expense:\n {}",
+            expenses_to_pretty(&expenses)
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(expenses_keyboard(&expenses))
+            .await?;
+        Ok(true)
+    }
+}
+
+async fn receive_group_id_for_edit_expense(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+
+            if send_modifiable_expenses(&bot, &msg, &ctl, group_id).await? {
+                dialogue
+                    .update(ChatState::ReceiveExpenseIdForEdit { group_id })
+                    .await?;
+            } else {
+                dialogue.update(ChatState::Start).await?;
+            }
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses an expense id, making sure it's one of the expenses the author can modify in the group
+async fn parse_modifiable_expense_id(
+    ctl: &Controller<'_>,
+    msg: &Message,
+    group_id: i64,
+) -> Result<Option<i64>, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(Ok(expense_id)) = msg.text().map(|x| x.trim().parse::<i64>()) else {
+        return Ok(None);
+    };
+
+    let author = get_author(ctl, msg).await?;
+    let expenses = ctl.get_modifiable_expenses(author.id, group_id).await?;
+
+    Ok(expenses
+        .iter()
+        .any(|x| x.id == expense_id)
+        .then_some(expense_id))
+}
+
+async fn receive_expense_id_for_edit(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+
+    if let Some(expense_id) = parse_modifiable_expense_id(&ctl, &msg, group_id).await? {
+        bot.send_message(
+            msg.chat.id,
+            "What would you like to change? Send `amount`, `note` or `participants`:",
+        )
+        .reply_markup(options_keyboard(&["amount", "note", "participants"]))
+        .await?;
+        dialogue
+            .update(ChatState::ReceiveExpenseField { expense_id })
+            .await?;
+    } else {
+        bot.send_message(msg.chat.id, "Please, provide id from the list: ")
+            .prompt(&msg)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn receive_expense_field(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    expense_id: i64,
+) -> HandlerResult {
+    if let Some(field) = msg.text() {
+        match field.trim().to_lowercase().as_str() {
+            "amount" => {
+                bot.send_message(msg.chat.id, "Type the new amount:")
+                    .prompt(&msg)
+                    .await?;
+                dialogue
+                    .update(ChatState::ReceiveNewAmount { expense_id })
+                    .await?;
+            }
+            "note" => {
+                bot.send_message(msg.chat.id, "Type the new note:")
+                    .prompt(&msg)
+                    .await?;
+                dialogue
+                    .update(ChatState::ReceiveNewNote { expense_id })
+                    .await?;
+            }
+            "participants" => {
+                bot.send_message(
+                    msg.chat.id,
+                    "How to split the expense? Send one of: `equal`, `exact`, `percent` or `shares`:",
+                )
+                .reply_markup(options_keyboard(SPLIT_MODES))
+                .await?;
+                dialogue
+                    .update(ChatState::ReceiveNewSplitMode { expense_id })
+                    .await?;
+            }
+            _ => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Please, send `amount`, `note` or `participants`:",
+                )
+                .prompt(&msg)
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_new_amount(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    expense_id: i64,
+) -> HandlerResult {
+    if let Some(amount) = msg.text() {
+        if let Ok(amount) = amount.parse::<Decimal>() {
+            if amount > 0.into() {
+                let ctl = Controller::from_msg(&bot, &msg).await?;
+                let author = get_author(&ctl, &msg).await?;
+
+                match ctl.edit_expense_amount(author.id, expense_id, amount).await {
+                    Ok(_) => {
+                        bot.send_message(msg.chat.id, "The expense has been updated")
+                            .await?;
+                        dialogue.update(ChatState::Start).await?;
+                    }
+                    Err(err) => match err.downcast_ref::<SplitError>() {
+                        Some(err) => {
+                            let text = format!(
+                                "{}. Change the participants first or type another amount:",
+                                err
+                            );
+                            bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+                        }
+                        None => return Err(err.into()),
+                    },
+                }
+            } else {
+                bot.send_message(msg.chat.id, "Please, provide some positive amount:")
+                    .prompt(&msg)
+                    .await?;
+            }
+        } else {
+            bot.send_message(msg.chat.id, "Please, provide some decimal value:")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_new_note(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    expense_id: i64,
+) -> HandlerResult {
+    if let Some(note) = msg.text() {
+        let ctl = Controller::from_msg(&bot, &msg).await?;
+        let author = get_author(&ctl, &msg).await?;
+
+        ctl.edit_expense_note(author.id, expense_id, note).await?;
+        bot.send_message(msg.chat.id, "The expense has been updated")
+            .await?;
+
+        dialogue.update(ChatState::Start).await?;
+    }
+
+    Ok(())
+}
+
+async fn receive_new_split_mode(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    expense_id: i64,
+) -> HandlerResult {
+    if let Some(text) = msg.text() {
+        if let Some(split_mode) = parse_split_mode(text) {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+            let author = get_author(&ctl, &msg).await?;
+            let expense = ctl.get_modifiable_expense(author.id, expense_id).await?;
+            let members = ctl.get_users_in_group(expense.group_id).await?;
+
+            let text = format!(
+                "{}:\n {}",
+                participants_hint(split_mode),
+                users_to_pretty(&members)
+            );
+            if split_mode == SplitMode::Equal {
+                bot.send_message(msg.chat.id, text)
+                    .reply_markup(options_keyboard(&["all"]))
+                    .await?;
+            } else {
+                bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+            }
+
+            dialogue
+                .update(ChatState::ReceiveNewParticipants {
+                    expense_id,
+                    split_mode,
+                })
+                .await?;
+        } else {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send one of: `equal`, `exact`, `percent` or `shares`:",
+            )
+            .prompt(&msg)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_new_participants(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    data: (i64, SplitMode),
+) -> HandlerResult {
+    let (expense_id, split_mode) = data;
+    if let Some(text) = msg.text() {
+        let ctl = Controller::from_msg(&bot, &msg).await?;
+        let author = get_author(&ctl, &msg).await?;
+        let expense = ctl.get_modifiable_expense(author.id, expense_id).await?;
+        let members = ctl.get_users_in_group(expense.group_id).await?;
+
+        let Some(participants) = parse_participants(text, &members, split_mode) else {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send participants in the requested format, using numbers from the list:",
+            )
+            .prompt(&msg)
+            .await?;
+            return Ok(());
+        };
+
+        if let Err(err) = settlement::validate_split(split_mode, expense.amount, &participants) {
+            let text = format!("{}. Please, try again:", err);
+            bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+            return Ok(());
+        }
+
+        ctl.edit_expense_participants(author.id, expense_id, split_mode, &participants)
+            .await?;
+        bot.send_message(msg.chat.id, "The expense has been updated")
+            .await?;
+
+        dialogue.update(ChatState::Start).await?;
+    }
+
+    Ok(())
+}
+
+async fn delete_expense(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_delete_expense(bot, dialogue, answer).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group where you'd like to delete the expense:\n {}",
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue
+            .update(ChatState::ReceiveGroupIdForDeleteExpense)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn receive_group_id_for_delete_expense(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
+
+            if send_modifiable_expenses(&bot, &msg, &ctl, group_id).await? {
+                dialogue
+                    .update(ChatState::ReceiveExpenseIdForDelete { group_id })
+                    .await?;
+            } else {
+                dialogue.update(ChatState::Start).await?;
+            }
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_expense_id_for_delete(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+
+    if let Some(expense_id) = parse_modifiable_expense_id(&ctl, &msg, group_id).await? {
+        let author = get_author(&ctl, &msg).await?;
+        ctl.delete_expense(author.id, expense_id).await?;
+
+        bot.send_message(msg.chat.id, "The expense has been deleted")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        bot.send_message(msg.chat.id, "Please, provide id from the list: ")
+            .prompt(&msg)
+            .await?;
+    }
 
-                let mut text = String::from("Group debt state:\n");
+    Ok(())
+}
 
-                if transactions.is_empty() {
-                    text.push_str("😊No debt in this group😊");
-                }
+async fn settle_up(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
 
-                for (x, y, amount) in transactions {
-                    let formatted_string = format!("😑{} owes {} to {}😑\n", x, amount, y);
-                    text.push_str(&formatted_string);
-                }
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_settle_up(bot, dialogue, answer).await;
+    }
 
-                text.push_str("\n --- \n Overall expenses in group:\n");
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group where you paid somebody back:\n {}",
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue
+            .update(ChatState::ReceiveGroupIdForSettleUp)
+            .await?;
+    }
 
-                for exp in expenses_in_group {
-                    let formatted_string = format!(
-                        "{} spent {} with note: {}\n",
-                        exp.username, exp.amount, exp.note
-                    );
-                    text.push_str(&formatted_string);
-                }
+    Ok(())
+}
 
-                bot.send_message(msg.chat.id, text).await?;
-            } else {
-                bot.send_message(msg.chat.id, "There are no expenses yet in this group")
-                    .await?;
-            }
+async fn receive_group_id_for_settle_up(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+) -> HandlerResult {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
+            let ctl = Controller::from_msg(&bot, &msg).await?;
 
-            dialogue.update(ChatState::Start).await?;
+            let members = ctl.get_users_in_group(group_id).await?;
+            let text = format!(
+                "Whom did you pay? Send the number of the member:\n {}",
+                users_to_pretty(&members)
+            );
+            bot.send_message(msg.chat.id, text)
+                .reply_markup(members_keyboard(&members))
+                .await?;
+            dialogue
+                .update(ChatState::ReceivePaymentRecipient { group_id })
+                .await?;
+        } else {
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
         }
     }
 
     Ok(())
 }
 
-async fn receive_note(
+async fn receive_payment_recipient(
     bot: Bot,
     dialogue: MyDialogue,
     msg: Message,
-    data: (i64, rust_decimal::Decimal),
+    group_id: i64,
 ) -> HandlerResult {
-    let (group_id, amount) = data;
-    if let Some(note) = msg.text() {
-        let username = get_author_username(&msg).await?;
+    if let Some(text) = msg.text() {
         let ctl = Controller::from_msg(&bot, &msg).await?;
+        let author = get_author(&ctl, &msg).await?;
+        let members = ctl.get_users_in_group(group_id).await?;
 
-        ctl.add_expense(&username, amount, group_id, note).await?;
-        bot.send_message(msg.chat.id, "The expense has been added")
-            .await?;
+        match find_member(text.trim(), &members) {
+            Some(member) if member.id != author.id => {
+                // Suggest the amount from the debt state, if the author owes anything to that member
+                let ledger = ctl.get_ledger(group_id).await?;
+                let owed = settlement::debts(&ledger)?
+                    .into_iter()
+                    .find(|x| x.from == author.id && x.to == member.id);
 
-        dialogue.update(ChatState::Start).await?;
+                let text = match owed {
+                    Some(owed) => format!(
+                        "How much {} did you pay to {}? You owe them {}:",
+                        ledger.currency,
+                        member.mention(),
+                        owed.amount
+                    ),
+                    None => format!(
+                        "How much {} did you pay to {}?",
+                        ledger.currency,
+                        member.mention()
+                    ),
+                };
+                bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+
+                dialogue
+                    .update(ChatState::ReceivePaymentAmount {
+                        group_id,
+                        to_user: member.id,
+                    })
+                    .await?;
+            }
+            Some(_) => {
+                bot.send_message(msg.chat.id, "You can't pay yourself, choose somebody else:")
+                    .prompt(&msg)
+                    .await?;
+            }
+            None => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Please, send the number of the member from the list:",
+                )
+                .prompt(&msg)
+                .await?;
+            }
+        }
     }
 
     Ok(())
 }
 
-async fn receive_amount_spent(
+async fn receive_payment_amount(
     bot: Bot,
     dialogue: MyDialogue,
     msg: Message,
-    group_id: i64,
+    data: (i64, i64),
 ) -> HandlerResult {
+    let (group_id, to_user) = data;
     if let Some(amount) = msg.text() {
         if let Ok(amount) = amount.parse::<Decimal>() {
             if amount > 0.into() {
-                bot.send_message(msg.chat.id, "Provide some note:").await?;
+                let ctl = Controller::from_msg(&bot, &msg).await?;
+                let author = get_author(&ctl, &msg).await?;
+                // Amounts too large or too precise for the currency of the group are asked again
+                let payment = match ctl.settle_up(group_id, author.id, to_user, amount).await {
+                    Ok(payment) => payment,
+                    Err(err) => match err.downcast_ref::<SplitError>() {
+                        Some(err) => {
+                            let text = format!("{}, please send the amount again:", err);
+                            bot.send_message(msg.chat.id, text).prompt(&msg).await?;
+                            return Ok(());
+                        }
+                        None => return Err(err.into()),
+                    },
+                };
 
-                dialogue
-                    .update(ChatState::ReceiveNote { group_id, amount })
-                    .await?;
+                let to_user = ctl
+                    .get_user_by_id(to_user)
+                    .await?
+                    .map(|x| x.mention())
+                    .unwrap_or_default();
+                let text = format!(
+                    "Recorded that you paid {} {} to {}",
+                    amount, payment.currency, to_user
+                );
+                bot.send_message(msg.chat.id, text).await?;
+
+                dialogue.update(ChatState::Start).await?;
             } else {
                 bot.send_message(msg.chat.id, "Please, provide some positive amount:")
+                    .prompt(&msg)
                     .await?;
             }
         } else {
             bot.send_message(msg.chat.id, "Please, provide some decimal value:")
+                .prompt(&msg)
                 .await?;
         }
     }
@@ -453,61 +3880,250 @@ async fn receive_amount_spent(
     Ok(())
 }
 
-async fn receive_user_name(
+async fn settle_all(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_settle_all(bot, dialogue, answer).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group where all the debts were paid:\n {}",
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue
+            .update(ChatState::ReceiveGroupIdForSettleAll)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn receive_group_id_for_settle_all(
     bot: Bot,
     dialogue: MyDialogue,
     msg: Message,
-    group_id: i64,
 ) -> HandlerResult {
-    if let Some(nickname) = msg.text() {
-        if nickname.is_empty() {
-            bot.send_message(
-                msg.chat.id,
-                "Please, provide a username, starting from `@`:",
-            )
-            .await?;
-        } else if nickname.starts_with('@') {
+    if let Some(group_id) = msg.text() {
+        if let Ok(group_id) = group_id.parse::<i64>() {
             let ctl = Controller::from_msg(&bot, &msg).await?;
-            ctl.add_user_to_a_group(nickname, group_id).await?;
-
-            let text = format!("User {} has been successfully added to a group", nickname);
-            bot.send_message(msg.chat.id, text).await?;
 
+            // Nothing is recorded until the transfers are confirmed
+            let intro = "These transfers will be marked as paid. Tap the button once all of them have been made:";
+            send_settle_all(&bot, &msg, &ctl, group_id, intro).await?;
             dialogue.update(ChatState::Start).await?;
         } else {
-            bot.send_message(
-                msg.chat.id,
-                "Please, provide a username, starting from `@`:",
+            bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Records the suggested transfers of a group as paid. If they aren't the ones `fingerprint` has
+/// been taken of, nothing is recorded and the current ones are shown instead
+async fn settle_group(
+    bot: &Bot,
+    msg: &Message,
+    ctl: &Controller<'_>,
+    group_id: i64,
+    fingerprint: u64,
+) -> HandlerResult {
+    let payments = match ctl.settle_all(group_id, fingerprint).await {
+        Ok(payments) => payments,
+        Err(err) if err.is::<DebtsChanged>() => {
+            let intro = "The debts have changed since, so nothing has been recorded. These are the debts now:";
+            return send_settle_all(bot, msg, ctl, group_id, intro).await;
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let text = if payments.is_empty() {
+        String::from("😊No debt in this group😊")
+    } else {
+        format!(
+            "Recorded {} payments, everybody in the group is settled now",
+            payments.len()
+        )
+    };
+    bot.send_message(msg.chat.id, text).await?;
+
+    Ok(())
+}
+
+/// Suggested transfers of a group along with the button that marks all of them as paid
+async fn send_settle_all(
+    bot: &Bot,
+    msg: &Message,
+    ctl: &Controller<'_>,
+    group_id: i64,
+    intro: &str,
+) -> HandlerResult {
+    let ledger = ctl.get_ledger(group_id).await?;
+    let transfers = settlement::debts(&ledger)?;
+    if transfers.is_empty() {
+        bot.send_message(msg.chat.id, "😊No debt in this group😊")
+            .await?;
+        return Ok(());
+    }
+
+    let people: Vec<user::Model> = ledger
+        .members
+        .iter()
+        .chain(ledger.former_members.iter())
+        .cloned()
+        .collect();
+    let text = format!(
+        "{}\n{}",
+        intro,
+        transfers_to_pretty(&people, &transfers, &ledger.currency)
+    );
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(settle_all_keyboard(group_id, &transfers))
+        .await?;
+
+    Ok(())
+}
+
+fn transfers_to_pretty(
+    people: &[user::Model],
+    transfers: &[settlement::Transfer],
+    currency: &str,
+) -> String {
+    transfers
+        .iter()
+        .map(|x| {
+            format!(
+                "😑{} owes {} {} to {}😑\n",
+                member_name(people, x.from),
+                x.amount,
+                currency,
+                member_name(people, x.to)
             )
+        })
+        .collect()
+}
+
+/// Data of the button which marks all debts of a group as paid, followed by the group id and the
+/// fingerprint of the transfers shown along with the button
+const SETTLE_ALL_BUTTON: &str = "settleall:";
+
+fn settle_all_keyboard(group_id: i64, transfers: &[settlement::Transfer]) -> InlineKeyboardMarkup {
+    let data = format!(
+        "{}{}:{}",
+        SETTLE_ALL_BUTTON,
+        group_id,
+        settlement::fingerprint(transfers)
+    );
+    keyboard([(String::from("Settle all"), data)])
+}
+
+fn parse_settle_all_button(msg: Message) -> Option<(i64, u64)> {
+    let (group_id, fingerprint) = msg
+        .text()?
+        .strip_prefix(SETTLE_ALL_BUTTON)?
+        .split_once(':')?;
+    Some((group_id.parse().ok()?, fingerprint.parse().ok()?))
+}
+
+/// Unlike `/settleall`, the button can be tapped in the middle of another dialogue, which is left as it is.
+/// Only the transfers shown along with the button are recorded
+async fn settle_all_button(bot: Bot, msg: Message, data: (i64, u64)) -> HandlerResult {
+    let (group_id, fingerprint) = data;
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+
+    settle_group(&bot, &msg, &ctl, group_id, fingerprint).await?;
+
+    Ok(())
+}
+
+async fn delete_payment(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+
+    if let Some(group) = ctl.get_chat_group().await? {
+        let answer = group_answer(&msg, &group)?;
+        return receive_group_id_for_delete_payment(bot, dialogue, answer).await;
+    }
+
+    let groups = ctl.get_user_groups(author.id).await?;
+    if groups.is_empty() {
+        bot.send_message(msg.chat.id, "You don't belong to any group yet")
+            .await?;
+        dialogue.update(ChatState::Start).await?;
+    } else {
+        let keyboard = groups_keyboard(&groups);
+        let groups = groups_to_pretty(groups);
+        let text = format!(
+            "Choose id of the group where you'd like to delete the payment:\n {}",
+            groups
+        );
+        bot.send_message(msg.chat.id, text)
+            .reply_markup(keyboard)
+            .await?;
+        dialogue
+            .update(ChatState::ReceiveGroupIdForDeletePayment)
             .await?;
-        }
     }
 
     Ok(())
 }
 
-async fn receive_group_id_for_add_member(
+async fn receive_group_id_for_delete_payment(
     bot: Bot,
     dialogue: MyDialogue,
     msg: Message,
 ) -> HandlerResult {
     if let Some(group_id) = msg.text() {
         if let Ok(group_id) = group_id.parse::<i64>() {
-            let username = get_author_username(&msg).await?;
             let ctl = Controller::from_msg(&bot, &msg).await?;
+            let author = get_author(&ctl, &msg).await?;
 
-            if ctl.user_is_in_group(&username, group_id).await? {
-                bot.send_message(msg.chat.id, "Provide @username of that user: ")
+            let payments = ctl.get_deletable_payments(author.id, group_id).await?;
+
+            if payments.is_empty() {
+                bot.send_message(
+                    msg.chat.id,
+                    "There are no payments you can delete in this group",
+                )
+                .await?;
+                dialogue.update(ChatState::Start).await?;
+            } else {
+                let ledger = ctl.get_ledger(group_id).await?;
+                let people: Vec<user::Model> = ledger
+                    .members
+                    .into_iter()
+                    .chain(ledger.former_members)
+                    .collect();
+                let text = format!(
+                    "Choose id of the payment:\n {}",
+                    payments_to_pretty(&people, &payments)
+                );
+                bot.send_message(msg.chat.id, text)
+                    .reply_markup(payments_keyboard(&people, &payments))
                     .await?;
                 dialogue
-                    .update(ChatState::ReceiveUsername { group_id })
-                    .await?;
-            } else {
-                bot.send_message(msg.chat.id, "Please, provide id from the list: ")
+                    .update(ChatState::ReceivePaymentIdForDelete { group_id })
                     .await?;
             }
         } else {
             bot.send_message(msg.chat.id, "Please, send an integer value: ")
+                .prompt(&msg)
                 .await?;
         }
     }
@@ -515,49 +4131,64 @@ async fn receive_group_id_for_add_member(
     Ok(())
 }
 
-async fn get_author_username(
-    msg: &Message,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    if let Some(user) = msg.from() {
-        if let Some(ref username) = user.username {
-            Ok(format!("@{}", username.to_owned()))
-        } else {
-            Err("😔Sorry, I can't detect your username😔".into())
+async fn receive_payment_id_for_delete(
+    bot: Bot,
+    dialogue: MyDialogue,
+    msg: Message,
+    group_id: i64,
+) -> HandlerResult {
+    let ctl = Controller::from_msg(&bot, &msg).await?;
+    let author = get_author(&ctl, &msg).await?;
+    let payments = ctl.get_deletable_payments(author.id, group_id).await?;
+
+    let payment_id = msg.text().and_then(|x| x.trim().parse::<i64>().ok());
+    match payment_id.filter(|id| payments.iter().any(|x| x.id == *id)) {
+        Some(payment_id) => {
+            ctl.delete_payment(author.id, payment_id).await?;
+
+            bot.send_message(
+                msg.chat.id,
+                "The payment has been deleted, the debts it paid off are owed again",
+            )
+            .await?;
+            dialogue.update(ChatState::Start).await?;
+        }
+        None => {
+            bot.send_message(msg.chat.id, "Please, provide id from the list: ")
+                .prompt(&msg)
+                .await?;
         }
-    } else {
-        Err("😔Sorry, I can't get info about you😔".into())
     }
-}
 
-async fn cancel(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
-    bot.send_message(msg.chat.id, "Canceled whatever you did")
-        .await?;
-    dialogue.exit().await?;
     Ok(())
 }
 
-async fn list_expenses_in_group(bot: Bot, msg: Message, dialogue: MyDialogue) -> HandlerResult {
-    let username = get_author_username(&msg).await?;
-    let ctl = Controller::from_msg(&bot, &msg).await?;
-
-    let groups = ctl.get_user_groups(&username).await?;
-    if groups.is_empty() {
-        bot.send_message(
-            msg.chat.id,
-            "You are not a member of any group, yet. You can create one with /creategroup",
-        )
-        .await?;
-    } else {
-        let groups = groups_to_pretty(groups);
-        let text = format!("Good, choose id of one of your groups:\n {}", groups);
-        bot.send_message(msg.chat.id, text).await?;
-    }
+fn payment_to_pretty(people: &[user::Model], payment: &payment::Model) -> String {
+    format!(
+        "{} — {} paid {} {} to {}",
+        payment.created_at.format("%Y-%m-%d"),
+        member_name(people, payment.from_user),
+        payment.amount,
+        payment.currency,
+        member_name(people, payment.to_user)
+    )
+}
 
-    dialogue
-        .update(ChatState::ReceiveGroupIdForExpensesList)
-        .await?;
+fn payments_to_pretty(people: &[user::Model], payments: &[payment::Model]) -> String {
+    payments
+        .iter()
+        .map(|x| format!("{} — {}\n", x.id, payment_to_pretty(people, x)))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
 
-    Ok(())
+/// Buttons that send the id of a payment
+fn payments_keyboard(people: &[user::Model], payments: &[payment::Model]) -> InlineKeyboardMarkup {
+    keyboard(
+        payments
+            .iter()
+            .map(|x| (payment_to_pretty(people, x), x.id.to_string())),
+    )
 }
 
 impl<'a> Controller<'a> {
@@ -584,7 +4215,64 @@ impl<'a> Controller<'a> {
     }
 }
 
+/// `Controller` refuses to touch groups of others, to let members do what only admins may and to
+/// change archived groups, and settlement refuses groups whose amounts overflow. Wherever a handler
+/// runs into that, the dialogue is over and the user is told why, instead of the error only ending up
+/// in the logs
+fn denial_handler() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync>> {
+    dptree::from_fn_with_description(
+        DpHandlerDescription::entry(),
+        |deps: DependencyMap, cont| async move {
+            let bot: Arc<Bot> = deps.get();
+            let update: Arc<Update> = deps.get();
+            let dialogue: Arc<MyDialogue> = deps.get();
+
+            let flow: ControlFlow<HandlerResult, DependencyMap> = cont(deps).await;
+            let text = match flow {
+                ControlFlow::Break(Err(ref err)) => denial_text(err.as_ref()),
+                _ => None,
+            };
+            match (text, update.chat()) {
+                (Some(text), Some(chat)) => {
+                    let result: HandlerResult = async {
+                        dialogue.exit().await?;
+                        bot.send_message(chat.id, text).await?;
+                        Ok(())
+                    }
+                    .await;
+                    ControlFlow::Break(result)
+                }
+                _ => flow,
+            }
+        },
+    )
+}
+
+/// What the user is told when `Controller` refused to do something for them, if that's the error
+fn denial_text(err: &(dyn std::error::Error + 'static)) -> Option<&'static str> {
+    std::iter::successors(Some(err), |x| x.source()).find_map(|x| {
+        if x.is::<NotAMember>() {
+            Some("😔Sorry, you aren't a member of that group😔")
+        } else if x.is::<NotAnAdmin>() {
+            Some("😔Sorry, only admins of the group can do that😔")
+        } else if x.is::<NotInGroup>() {
+            Some("😔Sorry, somebody you've chosen isn't a member of the group😔")
+        } else if x.is::<Archived>() {
+            Some("😔Sorry, the group is archived, /archivegroup brings it back😔")
+        } else if x.is::<settlement::Overflow>() {
+            Some("😔Sorry, amounts in the group are too large to add up😔")
+        } else {
+            None
+        }
+    })
+}
+
+/// In group chats people talk to each other as well, so whatever isn't meant for the bot is left alone
 async fn invalid_state(bot: Bot, msg: Message) -> HandlerResult {
+    if is_group_chat(&msg) {
+        return Ok(());
+    }
+
     bot.send_message(
         msg.chat.id,
         "Unable to handle the message. Type /help to see the usage.",
@@ -592,3 +4280,59 @@ async fn invalid_state(bot: Bot, msg: Message) -> HandlerResult {
     .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn currencies_are_parsed_in_any_case_if_known() {
+        assert_eq!(parse_currency(" eur "), Some("EUR".to_owned()));
+        assert_eq!(parse_currency("Usd"), Some("USD".to_owned()));
+        assert_eq!(parse_currency("ABC"), None);
+        assert_eq!(parse_currency("EURO"), None);
+    }
+
+    #[test]
+    fn group_args_take_only_known_currencies() {
+        assert_eq!(
+            parse_group_args("Trip EUR"),
+            ("Trip".to_owned(), Some("EUR".to_owned()))
+        );
+        assert_eq!(
+            parse_group_args("  Trip to Rome   JPY "),
+            ("Trip to Rome".to_owned(), Some("JPY".to_owned()))
+        );
+        assert_eq!(
+            parse_group_args("Trip to NYC"),
+            ("Trip to NYC".to_owned(), None)
+        );
+        assert_eq!(parse_group_args("Trip eur"), ("Trip eur".to_owned(), None));
+        assert_eq!(parse_group_args("Spa"), ("Spa".to_owned(), None));
+        assert_eq!(parse_group_args(""), (String::new(), None));
+    }
+
+    #[test]
+    fn expense_args_take_only_known_currencies() {
+        let draft = parse_expense_args("12.50 USD pizza with friends").unwrap();
+        assert_eq!(draft.amount, Decimal::new(1250, 2));
+        assert_eq!(draft.currency.as_deref(), Some("USD"));
+        assert_eq!(draft.note.as_deref(), Some("pizza with friends"));
+
+        let draft = parse_expense_args("30 BBQ at the park").unwrap();
+        assert_eq!(draft.amount, Decimal::from(30));
+        assert_eq!(draft.currency, None);
+        assert_eq!(draft.note.as_deref(), Some("BBQ at the park"));
+
+        let draft = parse_expense_args("30 tea").unwrap();
+        assert_eq!(draft.currency, None);
+        assert_eq!(draft.note.as_deref(), Some("tea"));
+
+        let draft = parse_expense_args("  7 EUR ").unwrap();
+        assert_eq!(draft.amount, Decimal::from(7));
+        assert_eq!(draft.currency.as_deref(), Some("EUR"));
+        assert_eq!(draft.note, None);
+
+        assert!(parse_expense_args("pizza 12").is_none());
+    }
+}
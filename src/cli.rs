@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use directories::BaseDirs;
 use once_cell::sync::Lazy;
 use std::{ffi::OsString, path::PathBuf};
@@ -6,7 +6,7 @@ use std::{ffi::OsString, path::PathBuf};
 pub static CLI: Lazy<Cli> = Lazy::new(parse_args);
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 pub struct Cli {
     #[arg(
         short,
@@ -17,8 +17,41 @@ pub struct Cli {
         default_value = get_default_database_file()
     )]
     pub database: PathBuf,
-    #[arg(short, long, value_name = "BOT TOKEN", env = "BOT_TOKEN")]
-    pub token: String,
+    #[arg(
+        short,
+        long,
+        value_name = "BOT TOKEN",
+        env = "BOT_TOKEN",
+        required = true
+    )]
+    pub token: Option<String>,
+    #[arg(
+        long = "admin",
+        env = "SPLITTEA_ADMINS",
+        value_name = "TELEGRAM USER ID",
+        value_delimiter = ',',
+        help = "Telegram user id allowed to run admin commands like /setrate, may be repeated"
+    )]
+    pub admins: Vec<u64>,
+    #[arg(
+        long,
+        env = "SPLITTEA_DIALOGUE_TIMEOUT",
+        value_name = "MINUTES",
+        default_value_t = 60,
+        help = "Unfinished dialogues are forgotten after this many minutes of silence, 0 keeps them forever"
+    )]
+    pub dialogue_timeout: u32,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Imports exchange rates from an ECB-style CSV or XML file, e.g. eurofxref-hist.csv
+    ImportRates {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
 }
 
 pub fn parse_args() -> Cli {
@@ -1,13 +1,149 @@
 use crate::{
+    cli::CLI,
     db,
-    entity::{expense, group},
+    entity::{exchange_rate, expense, group, invite, payment, user, user_group},
+    settlement,
 };
+use anyhow::Context;
+use chrono::NaiveDate;
+use rand::{distributions::Alphanumeric, Rng};
 use rust_decimal::Decimal;
+use sea_orm::Set;
 use teloxide::{
     types::{ChatId, UserId},
     Bot,
 };
 
+/// Returned when the user the bot is talking to reaches for a group they aren't a member of.
+/// Groups that don't exist are reported the same way, so their ids can't be probed
+#[derive(Debug)]
+pub struct NotAMember {
+    pub group_id: i64,
+}
+
+impl std::fmt::Display for NotAMember {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "User is not a member of group {}", self.group_id)
+    }
+}
+
+impl std::error::Error for NotAMember {}
+
+/// Returned when a member of a group attempts something only its admins may do
+#[derive(Debug)]
+pub struct NotAnAdmin {
+    pub group_id: i64,
+}
+
+impl std::fmt::Display for NotAnAdmin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "User is not an admin of group {}", self.group_id)
+    }
+}
+
+impl std::error::Error for NotAnAdmin {}
+
+/// Returned when an expense or a payment names somebody who isn't a member of its group
+#[derive(Debug)]
+pub struct NotInGroup {
+    pub user_id: i64,
+    pub group_id: i64,
+}
+
+impl std::fmt::Display for NotInGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "User {} is not a member of group {}",
+            self.user_id, self.group_id
+        )
+    }
+}
+
+impl std::error::Error for NotInGroup {}
+
+/// Returned when something is about to change in an archived group, which is read-only
+#[derive(Debug)]
+pub struct Archived {
+    pub group_id: i64,
+}
+
+impl std::fmt::Display for Archived {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Group {} is archived", self.group_id)
+    }
+}
+
+impl std::error::Error for Archived {}
+
+/// Reasons why a member can't be taken out of a group
+#[derive(Debug, PartialEq, Eq)]
+pub enum RemovalError {
+    Owner,
+    /// The owner is leaving, but nobody on Telegram is left to take the group over
+    NoHeir,
+    NotSettled,
+}
+
+impl std::fmt::Display for RemovalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Owner => write!(f, "The owner of a group can't be removed"),
+            Self::NoHeir => write!(f, "Nobody on Telegram is left to take the group over"),
+            Self::NotSettled => write!(f, "The member isn't settled up yet"),
+        }
+    }
+}
+
+impl std::error::Error for RemovalError {}
+
+/// Reasons why an invite link doesn't let anybody in
+#[derive(Debug, PartialEq, Eq)]
+pub enum InviteError {
+    Invalid,
+    Expired,
+    UsedUp,
+    /// The group has been archived since the invite was made
+    NoLongerValid,
+    /// Invites can't be valid for longer than `MAX_INVITE_DAYS`
+    TooLong,
+}
+
+/// How many days an invite may be valid for at most
+pub const MAX_INVITE_DAYS: u32 = 365;
+
+impl std::fmt::Display for InviteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Invalid => write!(f, "The invite link is invalid"),
+            Self::Expired => write!(f, "The invite link has expired"),
+            Self::UsedUp => write!(f, "The invite link has been used up"),
+            Self::NoLongerValid => write!(f, "The invite link is no longer valid"),
+            Self::TooLong => write!(
+                f,
+                "An invite link can be valid for {} days at most",
+                MAX_INVITE_DAYS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InviteError {}
+
+/// Returned when the suggested transfers of a group aren't the ones the user has been shown, e.g.
+/// an expense has been added since
+#[derive(Debug)]
+pub struct DebtsChanged;
+
+impl std::fmt::Display for DebtsChanged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The debts have changed since they were shown")
+    }
+}
+
+impl std::error::Error for DebtsChanged {}
+
+#[allow(unused)]
 pub struct Controller<'a> {
     pub bot: &'a Bot,
     pub db: &'a db::Database,
@@ -16,64 +152,1463 @@ pub struct Controller<'a> {
 }
 
 impl<'a> Controller<'a> {
-    pub async fn get_expenses_in_group(
+    /// Every operation on a group goes through here: it retrieves the group along with the membership
+    /// of the user the bot is talking to. Fails with `NotAMember` if they aren't a member
+    async fn authorize(&self, group_id: i64) -> anyhow::Result<(group::Model, user_group::Model)> {
+        let map_err = |err| anyhow::anyhow!("Retrieving membership failed. Err: {err}");
+        let group = self.get_group_by_id(group_id).await?;
+        let membership = match self.get_current_user().await? {
+            Some(user) => self
+                .db
+                .get_membership(group_id, user.id)
+                .await
+                .map_err(map_err)?,
+            None => None,
+        };
+
+        match (group, membership) {
+            (Some(group), Some(membership)) => Ok((group, membership)),
+            // With a context on top, `NotAMember` stays reachable through `source()` even after a
+            // handler turns the error into a boxed one
+            _ => Err(anyhow::Error::new(NotAMember { group_id }).context("Access denied")),
+        }
+    }
+
+    /// Retrieves a group the user is a member of
+    pub async fn authorize_group(&self, group_id: i64) -> anyhow::Result<group::Model> {
+        Ok(self.authorize(group_id).await?.0)
+    }
+
+    /// Retrieves a group the user is a member of for changing it, fails with `Archived` if it's archived
+    pub async fn authorize_change(&self, group_id: i64) -> anyhow::Result<group::Model> {
+        let group = self.authorize_group(group_id).await?;
+        ensure_active(&group)?;
+        Ok(group)
+    }
+
+    /// Retrieves a group the user is an admin of for changing it, fails with `NotAnAdmin` for its
+    /// other members
+    pub async fn authorize_admin(&self, group_id: i64) -> anyhow::Result<group::Model> {
+        let group = self.authorize_admin_incl_archived(group_id).await?;
+        ensure_active(&group)?;
+        Ok(group)
+    }
+
+    /// Same as `authorize_admin`, but lets archived groups through, since they can still be
+    /// unarchived or deleted
+    pub async fn authorize_admin_incl_archived(
         &self,
         group_id: i64,
-    ) -> anyhow::Result<Vec<expense::Model>> {
+    ) -> anyhow::Result<group::Model> {
+        let (group, membership) = self.authorize(group_id).await?;
+        if !membership.role.is_admin() {
+            return Err(anyhow::Error::new(NotAnAdmin { group_id }).context("Access denied"));
+        }
+
+        Ok(group)
+    }
+
+    /// Loads everything needed to compute the debt state of a group
+    pub async fn get_ledger(&self, group_id: i64) -> anyhow::Result<settlement::Ledger> {
+        let map_err = |err| anyhow::anyhow!("Retrieving group ledger failed. Err: {err}");
+        let group = self.authorize_group(group_id).await?;
+
+        let mut ledger = settlement::Ledger {
+            currency: group.currency,
+            simplification: group.simplification,
+            members: self
+                .db
+                .get_users_in_group(group_id)
+                .await
+                .map_err(map_err)?,
+            former_members: Vec::new(),
+            expenses: self
+                .db
+                .get_expenses_in_group(group_id)
+                .await
+                .map_err(map_err)?,
+            participants: self
+                .db
+                .get_participants_in_group(group_id)
+                .await
+                .map_err(map_err)?,
+            payers: self
+                .db
+                .get_payers_in_group(group_id)
+                .await
+                .map_err(map_err)?,
+            payments: self
+                .db
+                .get_payments_in_group(group_id)
+                .await
+                .map_err(map_err)?,
+        };
+
+        let mut mentioned: Vec<i64> = ledger
+            .expenses
+            .iter()
+            .flat_map(|x| [x.payer, x.created_by])
+            .chain(ledger.participants.iter().map(|x| x.user_id))
+            .chain(ledger.payers.iter().map(|x| x.user_id))
+            .chain(
+                ledger
+                    .payments
+                    .iter()
+                    .flat_map(|x| [x.from_user, x.to_user]),
+            )
+            .filter(|id| !ledger.members.iter().any(|x| x.id == *id))
+            .collect();
+        mentioned.sort_unstable();
+        mentioned.dedup();
+        if !mentioned.is_empty() {
+            ledger.former_members = self
+                .db
+                .get_users_by_ids(&mentioned)
+                .await
+                .map_err(map_err)?;
+        }
+
+        Ok(ledger)
+    }
+
+    /// Suggested transfers a member of a group takes part in. A member is settled up when there are none
+    pub async fn get_member_debts(
+        &self,
+        group_id: i64,
+        user_id: i64,
+    ) -> anyhow::Result<Vec<settlement::Transfer>> {
+        let ledger = self.get_ledger(group_id).await?;
+
+        Ok(settlement::debts(&ledger)
+            .context("Unable to add up the group")?
+            .into_iter()
+            .filter(|x| x.from == user_id || x.to == user_id)
+            .collect())
+    }
+
+    /// Takes a member out of a group, their expenses and payments stay. Anyone may leave, but only
+    /// admins can remove others and nobody can remove the owner. When the owner leaves, the group
+    /// passes to the longest-standing admin or, if there are none, member. Members who aren't settled
+    /// up stay, unless `force` is set
+    pub async fn remove_member(
+        &self,
+        group_id: i64,
+        user_id: i64,
+        force: bool,
+    ) -> anyhow::Result<()> {
+        let (group, caller) = self.authorize(group_id).await?;
+        ensure_active(&group)?;
+        let map_err = |err| anyhow::anyhow!("Retrieving memberships failed. Err: {err}");
+        let mut memberships = self
+            .db
+            .get_memberships_in_group(group_id)
+            .await
+            .map_err(map_err)?;
+        let membership = memberships
+            .iter()
+            .find(|x| x.user_id == user_id)
+            .cloned()
+            .ok_or(anyhow::anyhow!("The user isn't a member of the group"))?;
+
+        let leaving = caller.user_id == user_id;
+        if !leaving {
+            self.authorize_admin(group_id).await?;
+            if membership.role == user_group::Role::Owner {
+                return Err(RemovalError::Owner.into());
+            }
+        }
+
+        if !force && !self.get_member_debts(group_id, user_id).await?.is_empty() {
+            return Err(RemovalError::NotSettled.into());
+        }
+
+        let new_owner = if membership.role == user_group::Role::Owner {
+            // Placeholders can't manage anything, so they never take a group over
+            let users = self.get_users_in_group(group_id).await?;
+            memberships.retain(|x| {
+                x.user_id != user_id
+                    && users
+                        .iter()
+                        .any(|user| user.id == x.user_id && !user.is_placeholder())
+            });
+            memberships.sort_by_key(|x| (!x.role.is_admin(), x.created_at));
+            let heir = memberships.first().ok_or(RemovalError::NoHeir)?;
+            Some(heir.user_id)
+        } else {
+            None
+        };
+
         self.db
-            .get_expenses_in_group(group_id)
+            .remove_user_from_group(group_id, user_id, new_owner)
             .await
-            .map_err(|err| anyhow::anyhow!("Retrieving expenses failed. Err: {err}"))
+            .map_err(|err| anyhow::anyhow!("Removing member failed. Err: {err}"))
     }
 
-    pub async fn user_is_in_group(&self, username: &str, group_id: i64) -> anyhow::Result<bool> {
+    /// Records that `from_user` paid `amount` back to `to_user`
+    pub async fn settle_up(
+        &self,
+        group_id: i64,
+        from_user: i64,
+        to_user: i64,
+        amount: Decimal,
+    ) -> anyhow::Result<payment::Model> {
+        let group = self.authorize_change(group_id).await?;
+        settlement::validate_amount(amount)?;
+        settlement::validate_minor_units(amount, &group.currency)?;
+        if from_user == to_user {
+            anyhow::bail!("A member can't pay themselves");
+        }
+        self.ensure_members(group_id, [from_user, to_user]).await?;
+
+        let mut payments = self
+            .db
+            .insert_payments(group_id, &group.currency, &[(from_user, to_user, amount)])
+            .await
+            .map_err(|err| anyhow::anyhow!("Payment insertion failed. Err: {err}"))?;
+
+        payments
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("Payment insertion failed"))
+    }
+
+    /// Records every suggested transfer of a group as paid, as long as they are still the ones
+    /// `settlement::fingerprint` has been taken of
+    pub async fn settle_all(
+        &self,
+        group_id: i64,
+        fingerprint: u64,
+    ) -> anyhow::Result<Vec<payment::Model>> {
+        self.authorize_change(group_id).await?;
+        let ledger = self.get_ledger(group_id).await?;
+        let debts = settlement::debts(&ledger).context("Unable to add up the group")?;
+        if settlement::fingerprint(&debts) != fingerprint {
+            return Err(DebtsChanged.into());
+        }
+
+        let transfers: Vec<(i64, i64, Decimal)> = debts
+            .into_iter()
+            .map(|x| (x.from, x.to, x.amount))
+            .collect();
+
+        self.db
+            .insert_payments(group_id, &ledger.currency, &transfers)
+            .await
+            .map_err(|err| anyhow::anyhow!("Payment insertion failed. Err: {err}"))
+    }
+
+    /// Payments in a group which the user is allowed to delete
+    pub async fn get_deletable_payments(
+        &self,
+        user_id: i64,
+        group_id: i64,
+    ) -> anyhow::Result<Vec<payment::Model>> {
+        let (group, membership) = self.authorize(group_id).await?;
+        ensure_active(&group)?;
+
+        let payments = self
+            .db
+            .get_payments_in_group(group_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving payments failed. Err: {err}"))?;
+
+        Ok(payments
+            .into_iter()
+            .filter(|x| can_delete_payment(user_id, membership.role, x))
+            .collect())
+    }
+
+    /// Deletes a payment recorded by mistake, the debts it has paid off are owed again
+    pub async fn delete_payment(&self, user_id: i64, payment_id: i64) -> anyhow::Result<()> {
+        let payment = self
+            .db
+            .get_payment_by_id(payment_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving payment failed. Err: {err}"))?
+            .ok_or(anyhow::anyhow!("Inexistent payment id"))?;
+        let (group, membership) = self.authorize(payment.group_id).await?;
+        ensure_active(&group)?;
+
+        if !can_delete_payment(user_id, membership.role, &payment) {
+            anyhow::bail!("Only those who made or received the payment or an admin can delete it");
+        }
+
+        self.db
+            .delete_payment(payment_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Payment deletion failed. Err: {err}"))
+    }
+
+    pub async fn get_users_in_group(&self, group_id: i64) -> anyhow::Result<Vec<user::Model>> {
+        self.authorize_group(group_id).await?;
+
+        self.db
+            .get_users_in_group(group_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving users in group failed. Err: {err}"))
+    }
+
+    /// Fails with `NotInGroup` unless every one of the users is a member of the group
+    async fn ensure_members(
+        &self,
+        group_id: i64,
+        user_ids: impl IntoIterator<Item = i64>,
+    ) -> anyhow::Result<()> {
+        let members = self
+            .db
+            .get_users_in_group(group_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving users in group failed. Err: {err}"))?;
+
+        match user_ids
+            .into_iter()
+            .find(|id| !members.iter().any(|x| x.id == *id))
+        {
+            Some(user_id) => {
+                Err(anyhow::Error::new(NotInGroup { user_id, group_id }).context("Invalid member"))
+            }
+            None => Ok(()),
+        }
+    }
+
+    async fn user_is_in_group(&self, user_id: i64, group_id: i64) -> anyhow::Result<bool> {
         let users_in_group = self
             .db
             .get_users_in_group(group_id)
             .await
             .map_err(|err| anyhow::anyhow!("Retrieving users in group failed. Err: {err}"))?;
 
-        Ok(users_in_group.iter().any(|uig| uig.username == username))
+        Ok(users_in_group.iter().any(|uig| uig.id == user_id))
     }
 
     pub async fn add_expense(
         &self,
-        username: &str,
-        amount: Decimal,
+        new_expense: &db::NewExpense,
+    ) -> anyhow::Result<expense::Model> {
+        self.authorize_change(new_expense.group_id).await?;
+        settlement::validate_split(
+            new_expense.split_mode,
+            new_expense.amount,
+            &new_expense.participants,
+        )?;
+        settlement::validate_amount(new_expense.amount)?;
+        settlement::validate_minor_units(new_expense.amount, &new_expense.currency)?;
+        settlement::validate_payers(new_expense.amount, &new_expense.payers)?;
+        settlement::validate_rate(new_expense.exchange_rate)?;
+        ensure_currency(&new_expense.currency)?;
+        self.ensure_members(
+            new_expense.group_id,
+            std::iter::once(new_expense.payer)
+                .chain(new_expense.payers.iter().map(|x| x.0))
+                .chain(new_expense.participants.iter().map(|x| x.0)),
+        )
+        .await?;
+
+        self.db
+            .insert_expense(new_expense)
+            .await
+            .map_err(|err| anyhow::anyhow!("Expense insertion failed. Err: {err}"))
+    }
+
+    /// Expenses in a group which the user is allowed to edit or delete
+    pub async fn get_modifiable_expenses(
+        &self,
+        user_id: i64,
         group_id: i64,
+    ) -> anyhow::Result<Vec<expense::Model>> {
+        let (group, membership) = self.authorize(group_id).await?;
+        ensure_active(&group)?;
+
+        let expenses = self
+            .db
+            .get_expenses_in_group(group_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving expenses failed. Err: {err}"))?;
+
+        Ok(expenses
+            .into_iter()
+            .filter(|x| can_modify_expense(user_id, membership.role, x))
+            .collect())
+    }
+
+    /// Retrieves an expense, making sure that the user is allowed to edit or delete it
+    pub async fn get_modifiable_expense(
+        &self,
+        user_id: i64,
+        expense_id: i64,
+    ) -> anyhow::Result<expense::Model> {
+        let expense = self
+            .db
+            .get_expense_by_id(expense_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving expense failed. Err: {err}"))?
+            .ok_or(anyhow::anyhow!("Inexistent expense id"))?;
+        let (group, membership) = self.authorize(expense.group_id).await?;
+        ensure_active(&group)?;
+
+        if !can_modify_expense(user_id, membership.role, &expense) {
+            anyhow::bail!("Only the one who logged the expense or an admin can modify it");
+        }
+
+        Ok(expense)
+    }
+
+    pub async fn edit_expense_amount(
+        &self,
+        user_id: i64,
+        expense_id: i64,
+        amount: Decimal,
+    ) -> anyhow::Result<expense::Model> {
+        let expense = self.get_modifiable_expense(user_id, expense_id).await?;
+
+        // Shares and contributions of payers must still add up to the new amount
+        let map_err = |err| anyhow::anyhow!("Retrieving expense details failed. Err: {err}");
+        let participants: Vec<(i64, Option<Decimal>)> = self
+            .db
+            .get_expense_participants(expense_id)
+            .await
+            .map_err(map_err)?
+            .into_iter()
+            .map(|x| (x.user_id, x.share))
+            .collect();
+        let payers: Vec<(i64, Decimal)> = self
+            .db
+            .get_expense_payers(expense_id)
+            .await
+            .map_err(map_err)?
+            .into_iter()
+            .map(|x| (x.user_id, x.amount))
+            .collect();
+        settlement::validate_amount(amount)?;
+        settlement::validate_minor_units(amount, &expense.currency)?;
+        settlement::validate_split(expense.split_mode, amount, &participants)?;
+        settlement::validate_payers(amount, &payers)?;
+
+        let mut expense: expense::ActiveModel = expense.into();
+        expense.amount = Set(amount);
+        self.db
+            .update_expense(expense)
+            .await
+            .map_err(|err| anyhow::anyhow!("Expense update failed. Err: {err}"))
+    }
+
+    pub async fn edit_expense_note(
+        &self,
+        user_id: i64,
+        expense_id: i64,
         note: &str,
     ) -> anyhow::Result<expense::Model> {
+        let expense = self.get_modifiable_expense(user_id, expense_id).await?;
+
+        let mut expense: expense::ActiveModel = expense.into();
+        expense.note = Set(note.to_owned());
         self.db
-            .insert_expense(username, amount, group_id, note)
+            .update_expense(expense)
             .await
-            .map_err(|err| anyhow::anyhow!("Expense insertion failed. Err: {err}"))
+            .map_err(|err| anyhow::anyhow!("Expense update failed. Err: {err}"))
+    }
+
+    pub async fn edit_expense_participants(
+        &self,
+        user_id: i64,
+        expense_id: i64,
+        split_mode: expense::SplitMode,
+        participants: &[(i64, Option<Decimal>)],
+    ) -> anyhow::Result<expense::Model> {
+        let expense = self.get_modifiable_expense(user_id, expense_id).await?;
+        settlement::validate_split(split_mode, expense.amount, participants)?;
+        self.ensure_members(expense.group_id, participants.iter().map(|x| x.0))
+            .await?;
+
+        self.db
+            .replace_expense_participants(expense, split_mode, participants)
+            .await
+            .map_err(|err| anyhow::anyhow!("Expense update failed. Err: {err}"))
+    }
+
+    pub async fn delete_expense(&self, user_id: i64, expense_id: i64) -> anyhow::Result<()> {
+        self.get_modifiable_expense(user_id, expense_id).await?;
+
+        self.db
+            .delete_expense(expense_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Expense deletion failed. Err: {err}"))
+    }
+
+    /// Creates a group with the user as its first member, bound to the current chat if `bind_chat` is set
+    pub async fn create_group(
+        &self,
+        group_name: &str,
+        currency: &str,
+        bind_chat: bool,
+    ) -> anyhow::Result<group::Model> {
+        ensure_currency(currency)?;
+        let chat_id = bind_chat.then_some(self.chat_id.0);
+        let group = self
+            .db
+            .insert_group(group_name, currency, chat_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Group creation failed. Err: {err}"))?;
+        let user = self
+            .get_current_user()
+            .await?
+            .ok_or(anyhow::anyhow!("User not found"))?;
+        self.db
+            .add_user_to_group(group.id, user.id, user_group::Role::Owner)
+            .await
+            .map_err(|err| anyhow::anyhow!("Adding user to group failed. Err: {err}"))?;
+
+        Ok(group)
     }
 
-    pub async fn create_group(&self, group_name: &str) -> anyhow::Result<group::Model> {
+    /// Makes the user a member of the group bound to the current chat. Being in the chat is
+    /// what entitles them to it, so it's the only way into a group without being added. Those who
+    /// left the group or were removed from it stay out until they are added or invited again
+    pub async fn join_chat_group(&self) -> anyhow::Result<()> {
+        let Some(group) = self.get_chat_group().await? else {
+            return Ok(());
+        };
+
+        let user = self
+            .get_current_user()
+            .await?
+            .ok_or(anyhow::anyhow!("User not found"))?;
+        let has_left = self
+            .db
+            .has_left_group(group.id, user.id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving departures failed. Err: {err}"))?;
+        if !has_left && !self.user_is_in_group(user.id, group.id).await? {
+            self.db
+                .add_user_to_group(group.id, user.id, user_group::Role::Member)
+                .await
+                .map_err(|err| anyhow::anyhow!("Adding user to group failed. Err: {err}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates an invite to a group, which works for `valid_for` and lets `max_uses` people in.
+    /// Either of them can be unlimited
+    pub async fn create_invite(
+        &self,
+        group_id: i64,
+        valid_for: Option<chrono::Duration>,
+        max_uses: Option<i32>,
+    ) -> anyhow::Result<invite::Model> {
+        self.authorize_change(group_id).await?;
+        let user = self
+            .get_current_user()
+            .await?
+            .ok_or(anyhow::anyhow!("User not found"))?;
+
+        let expires_at = match valid_for {
+            Some(x) if x > chrono::Duration::days(MAX_INVITE_DAYS.into()) => {
+                return Err(InviteError::TooLong.into())
+            }
+            Some(x) => Some(
+                chrono::Utc::now()
+                    .checked_add_signed(x)
+                    .ok_or(InviteError::TooLong)?,
+            ),
+            None => None,
+        };
         self.db
-            .insert_group(group_name)
+            .insert_invite(group_id, user.id, &invite_token(), expires_at, max_uses)
+            .await
+            .map_err(|err| anyhow::anyhow!("Invite creation failed. Err: {err}"))
+    }
+
+    /// Makes the user a member of the group an invite leads to. Along with the group, tells whether
+    /// they have just joined it, since members who open an invite once again stay as they are
+    pub async fn join_by_invite(&self, token: &str) -> anyhow::Result<(group::Model, bool)> {
+        let invite = self
+            .db
+            .get_invite_by_token(token)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving invite failed. Err: {err}"))?
+            .ok_or(InviteError::Invalid)?;
+        if invite.expires_at.is_some_and(|x| x <= chrono::Utc::now()) {
+            return Err(InviteError::Expired.into());
+        }
+
+        let group = self
+            .get_group_by_id(invite.group_id)
+            .await?
+            .ok_or(InviteError::Invalid)?;
+        // Those following the link may not even be members, so nothing is told about the archive
+        if group.is_archived() {
+            return Err(InviteError::NoLongerValid.into());
+        }
+
+        let user = self
+            .get_current_user()
+            .await?
+            .ok_or(anyhow::anyhow!("User not found"))?;
+        if self.user_is_in_group(user.id, group.id).await? {
+            return Ok((group, false));
+        }
+
+        let joined = self
+            .db
+            .redeem_invite(&invite, user.id)
             .await
-            .map_err(|err| anyhow::anyhow!("Group creation failed. Err: {err}"))
+            .map_err(|err| anyhow::anyhow!("Joining by invite failed. Err: {err}"))?;
+        if !joined {
+            return Err(InviteError::UsedUp.into());
+        }
+
+        Ok((group, true))
     }
 
-    pub async fn add_user_to_a_group(&self, username: &str, group_id: i64) -> anyhow::Result<()> {
+    /// Group bound to the current chat, if any
+    pub async fn get_chat_group(&self) -> anyhow::Result<Option<group::Model>> {
         self.db
-            .add_user_to_group(group_id, username)
+            .get_group_by_chat(self.chat_id.0)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving chat group failed. Err: {err}"))
+    }
+
+    /// Changes the currency of a group. Amounts are kept in the currency they were recorded in, so
+    /// it's only possible before the group has any expenses or payments, otherwise `None` is returned
+    pub async fn set_group_currency(
+        &self,
+        group_id: i64,
+        currency: &str,
+    ) -> anyhow::Result<Option<group::Model>> {
+        let group = self.authorize_admin(group_id).await?;
+        ensure_currency(currency)?;
+        let ledger = self.get_ledger(group_id).await?;
+        if !ledger.expenses.is_empty() || !ledger.payments.is_empty() {
+            return Ok(None);
+        }
+
+        self.db
+            .set_group_currency(group, currency)
+            .await
+            .map(Some)
+            .map_err(|err| anyhow::anyhow!("Changing group currency failed. Err: {err}"))
+    }
+
+    pub async fn set_group_simplification(
+        &self,
+        group_id: i64,
+        simplification: group::Simplification,
+    ) -> anyhow::Result<group::Model> {
+        let group = self.authorize_admin(group_id).await?;
+        self.db
+            .set_group_simplification(group, simplification)
+            .await
+            .map_err(|err| anyhow::anyhow!("Changing group simplification failed. Err: {err}"))
+    }
+
+    pub async fn rename_group(&self, group_id: i64, name: &str) -> anyhow::Result<group::Model> {
+        let group = self.authorize_admin(group_id).await?;
+        self.db
+            .rename_group(group, name)
+            .await
+            .map_err(|err| anyhow::anyhow!("Renaming group failed. Err: {err}"))
+    }
+
+    /// Archives a group or brings an archived one back into use
+    pub async fn set_group_archived(
+        &self,
+        group_id: i64,
+        archived: bool,
+    ) -> anyhow::Result<group::Model> {
+        let group = self.authorize_admin_incl_archived(group_id).await?;
+        self.db
+            .set_group_archived(group, archived)
+            .await
+            .map_err(|err| anyhow::anyhow!("Archiving group failed. Err: {err}"))
+    }
+
+    /// Deletes a group for all of its members along with its expenses and payments
+    pub async fn delete_group(&self, group_id: i64) -> anyhow::Result<()> {
+        self.authorize_admin_incl_archived(group_id).await?;
+        self.db
+            .delete_group(group_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Group deletion failed. Err: {err}"))
+    }
+
+    pub async fn add_user_to_a_group(&self, user_id: i64, group_id: i64) -> anyhow::Result<()> {
+        self.authorize_change(group_id).await?;
+
+        self.db
+            .add_user_to_group(group_id, user_id, user_group::Role::Member)
             .await
             .map_err(|err| anyhow::anyhow!("Adding user to group failed. Err: {err}"))
     }
 
-    pub async fn get_user_groups(&self, username: &str) -> anyhow::Result<Vec<group::Model>> {
+    /// Members of a group who have `role`
+    pub async fn get_members_with_role(
+        &self,
+        group_id: i64,
+        role: user_group::Role,
+    ) -> anyhow::Result<Vec<user::Model>> {
+        let ids: Vec<i64> = self
+            .db
+            .get_memberships_in_group(group_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving memberships failed. Err: {err}"))?
+            .into_iter()
+            .filter(|x| x.role == role)
+            .map(|x| x.user_id)
+            .collect();
+
+        Ok(self
+            .get_users_in_group(group_id)
+            .await?
+            .into_iter()
+            .filter(|x| ids.contains(&x.id))
+            .collect())
+    }
+
+    /// Makes a member an admin or the other way around. Only admins can do it and nobody can take
+    /// the group away from its owner
+    pub async fn set_member_role(
+        &self,
+        group_id: i64,
+        user_id: i64,
+        role: user_group::Role,
+    ) -> anyhow::Result<()> {
+        self.authorize_admin(group_id).await?;
+        if role == user_group::Role::Owner {
+            anyhow::bail!("A group has a single owner");
+        }
+
+        let membership = self
+            .db
+            .get_membership(group_id, user_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving membership failed. Err: {err}"))?
+            .ok_or(anyhow::anyhow!("The user isn't a member of the group"))?;
+        if membership.role == user_group::Role::Owner {
+            anyhow::bail!("The owner of a group can't be demoted");
+        }
+
+        self.db
+            .set_member_role(membership, role)
+            .await
+            .map(|_| ())
+            .map_err(|err| anyhow::anyhow!("Changing member role failed. Err: {err}"))
+    }
+
+    /// Groups of the user, except for the archived ones
+    pub async fn get_user_groups(&self, user_id: i64) -> anyhow::Result<Vec<group::Model>> {
+        let mut groups = self.get_all_user_groups(user_id).await?;
+        groups.retain(|x| !x.is_archived());
+        Ok(groups)
+    }
+
+    /// Groups of the user, archived ones included
+    pub async fn get_all_user_groups(&self, user_id: i64) -> anyhow::Result<Vec<group::Model>> {
         self.db
-            .get_user_groups(username)
+            .get_user_groups(user_id)
             .await
             .map_err(|err| anyhow::anyhow!("Retrieving user groups failed. Err: {err}"))
     }
 
-    pub async fn get_group_by_id(&self, group_id: i64) -> anyhow::Result<Option<group::Model>> {
+    /// Registers the user the bot is talking to or refreshes their @username and name.
+    /// Placeholder members added by the same @username become this user
+    pub async fn sync_user(
+        &self,
+        username: Option<&str>,
+        display_name: &str,
+    ) -> anyhow::Result<user::Model> {
+        self.db
+            .sync_user(self.user_id.0 as i64, username, display_name)
+            .await
+            .map_err(|err| anyhow::anyhow!("Syncing user failed. Err: {err}"))
+    }
+
+    /// Finds a user by @username, adding a placeholder if nobody is known by it yet
+    pub async fn get_or_add_user(&self, username: &str) -> anyhow::Result<user::Model> {
+        self.db
+            .get_or_insert_user_by_username(username)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving user failed. Err: {err}"))
+    }
+
+    /// Adds a named member who isn't on Telegram to a group
+    pub async fn add_placeholder_to_a_group(
+        &self,
+        name: &str,
+        group_id: i64,
+    ) -> anyhow::Result<user::Model> {
+        self.authorize_change(group_id).await?;
+
+        let user = self
+            .db
+            .insert_placeholder_user(name)
+            .await
+            .map_err(|err| anyhow::anyhow!("Placeholder insertion failed. Err: {err}"))?;
+        self.add_user_to_a_group(user.id, group_id).await?;
+
+        Ok(user)
+    }
+
+    /// Turns a placeholder member of a group into a real user, keeping all their expenses and payments.
+    /// Only admins may do that, and only into themselves or somebody already in the group, so that
+    /// nobody's history can be handed over to a stranger
+    pub async fn merge_placeholder(
+        &self,
+        group_id: i64,
+        placeholder_id: i64,
+        user_id: i64,
+    ) -> anyhow::Result<()> {
+        self.authorize_admin(group_id).await?;
+        let members = self.get_users_in_group(group_id).await?;
+        if !members
+            .iter()
+            .any(|x| x.id == placeholder_id && x.is_placeholder())
+        {
+            anyhow::bail!("Only placeholder members of the group can be merged");
+        }
+
+        if placeholder_id == user_id {
+            anyhow::bail!("Can't merge a placeholder into itself");
+        }
+
+        let is_caller = self
+            .get_current_user()
+            .await?
+            .is_some_and(|x| x.id == user_id);
+        let is_member = members
+            .iter()
+            .any(|x| x.id == user_id && !x.is_placeholder());
+        if !is_caller && !is_member {
+            anyhow::bail!(
+                "A placeholder can only be merged into the caller or a member of the group"
+            );
+        }
+
+        self.db
+            .merge_users(placeholder_id, user_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Merging users failed. Err: {err}"))
+    }
+
+    /// Admins of the bot are given on the command line
+    pub fn is_admin(&self) -> bool {
+        CLI.admins.contains(&self.user_id.0)
+    }
+
+    pub async fn set_exchange_rate(
+        &self,
+        date: NaiveDate,
+        from: &str,
+        to: &str,
+        rate: Decimal,
+    ) -> anyhow::Result<()> {
+        if !self.is_admin() {
+            anyhow::bail!("Only admins can set exchange rates");
+        }
+        settlement::validate_rate(rate)?;
+
+        let rate = exchange_rate::Model {
+            date,
+            from_currency: from.to_owned(),
+            to_currency: to.to_owned(),
+            rate,
+        };
+        self.db
+            .upsert_exchange_rates(&[rate])
+            .await
+            .map_err(|err| anyhow::anyhow!("Exchange rate insertion failed. Err: {err}"))
+    }
+
+    /// Rate an expense made on `date` locks in
+    pub async fn find_exchange_rate(
+        &self,
+        date: NaiveDate,
+        from: &str,
+        to: &str,
+    ) -> anyhow::Result<Option<exchange_rate::Model>> {
+        self.db
+            .find_exchange_rate(date, from, to)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving exchange rate failed. Err: {err}"))
+    }
+
+    pub async fn get_user_by_id(&self, user_id: i64) -> anyhow::Result<Option<user::Model>> {
+        self.db
+            .get_user_by_id(user_id)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving user by id failed. Err: {err}"))
+    }
+
+    /// The user the bot is talking to, if they have been synced already
+    pub async fn get_current_user(&self) -> anyhow::Result<Option<user::Model>> {
+        self.db
+            .get_user_by_telegram_id(self.user_id.0 as i64)
+            .await
+            .map_err(|err| anyhow::anyhow!("Retrieving current user failed. Err: {err}"))
+    }
+
+    async fn get_group_by_id(&self, group_id: i64) -> anyhow::Result<Option<group::Model>> {
         self.db
             .get_group_by_id(group_id)
             .await
             .map_err(|err| anyhow::anyhow!("Retrieving group by id failed. Err: {err}"))
     }
 }
+
+/// Fails for anything but the codes of ISO 4217, so that groups and expenses can't carry made-up ones
+fn ensure_currency(currency: &str) -> anyhow::Result<()> {
+    if !settlement::is_currency(currency) {
+        anyhow::bail!("Unknown currency {currency}");
+    }
+
+    Ok(())
+}
+
+fn ensure_active(group: &group::Model) -> anyhow::Result<()> {
+    if group.is_archived() {
+        return Err(anyhow::Error::new(Archived { group_id: group.id }).context("Access denied"));
+    }
+
+    Ok(())
+}
+
+/// Random string to put in an invite link. Telegram lets `/start` payloads have up to 64 letters,
+/// digits, `_` and `-`
+fn invite_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(16)
+        .map(char::from)
+        .collect()
+}
+
+/// Only the one who logged an expense and admins of its group may edit or delete it
+fn can_modify_expense(user_id: i64, role: user_group::Role, expense: &expense::Model) -> bool {
+    expense.created_by == user_id || role.is_admin()
+}
+
+/// Both sides of a payment and admins of its group may delete it
+fn can_delete_payment(user_id: i64, role: user_group::Role, payment: &payment::Model) -> bool {
+    payment.from_user == user_id || payment.to_user == user_id || role.is_admin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Migrated database of its own, so that tests don't share any state
+    async fn database() -> db::Database {
+        let db = db::Database::in_memory().await.unwrap();
+        db.apply_migrations().await.unwrap();
+        db
+    }
+
+    /// Controller acting for the Telegram user `telegram_id` in their private chat
+    async fn controller<'a>(
+        bot: &'a Bot,
+        db: &'a db::Database,
+        telegram_id: u64,
+    ) -> Controller<'a> {
+        let ctl = Controller {
+            bot,
+            db,
+            user_id: UserId(telegram_id),
+            chat_id: ChatId(telegram_id as i64),
+        };
+        ctl.sync_user(None, &format!("user {}", telegram_id))
+            .await
+            .unwrap();
+        ctl
+    }
+
+    /// Controller acting for the Telegram user `telegram_id` in the group chat `chat_id`
+    async fn chat_controller<'a>(
+        bot: &'a Bot,
+        db: &'a db::Database,
+        telegram_id: u64,
+        chat_id: i64,
+    ) -> Controller<'a> {
+        Controller {
+            chat_id: ChatId(chat_id),
+            ..controller(bot, db, telegram_id).await
+        }
+    }
+
+    fn is_not_a_member<T>(result: anyhow::Result<T>) -> bool {
+        result.is_err_and(|err| err.downcast_ref::<NotAMember>().is_some())
+    }
+
+    fn is_not_in_group<T>(result: anyhow::Result<T>) -> bool {
+        result.is_err_and(|err| err.downcast_ref::<NotInGroup>().is_some())
+    }
+
+    fn is_split_error<T>(result: anyhow::Result<T>, expected: settlement::SplitError) -> bool {
+        result.is_err_and(|err| err.downcast_ref::<settlement::SplitError>() == Some(&expected))
+    }
+
+    fn is_not_an_admin<T>(result: anyhow::Result<T>) -> bool {
+        result.is_err_and(|err| err.downcast_ref::<NotAnAdmin>().is_some())
+    }
+
+    #[tokio::test]
+    async fn made_up_currencies_are_refused() {
+        let bot = Bot::new("token");
+        let db = database().await;
+        let owner = controller(&bot, &db, 1).await;
+
+        assert!(owner.create_group("Trip", "ABC", false).await.is_err());
+        let group = owner.create_group("Trip", "EUR", false).await.unwrap();
+        assert!(owner.set_group_currency(group.id, "ABC").await.is_err());
+        assert!(owner
+            .set_group_currency(group.id, "USD")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn currency_is_kept_once_the_group_has_history() {
+        let bot = Bot::new("token");
+        let db = database().await;
+        let owner = controller(&bot, &db, 1).await;
+        let group = owner.create_group("Trip", "EUR", false).await.unwrap();
+        let owner_id = owner.get_current_user().await.unwrap().unwrap().id;
+        let member = owner
+            .add_placeholder_to_a_group("Bob", group.id)
+            .await
+            .unwrap();
+
+        owner
+            .settle_up(group.id, member.id, owner_id, Decimal::TEN)
+            .await
+            .unwrap();
+        assert!(owner
+            .set_group_currency(group.id, "USD")
+            .await
+            .unwrap()
+            .is_none());
+
+        let ledger = owner.get_ledger(group.id).await.unwrap();
+        assert_eq!(ledger.currency, "EUR");
+        assert_eq!(ledger.payments[0].currency, "EUR");
+    }
+
+    #[tokio::test]
+    async fn groups_of_others_are_refused() {
+        let bot = Bot::new("token");
+        let db = database().await;
+        let owner = controller(&bot, &db, 1).await;
+        let stranger = controller(&bot, &db, 2).await;
+        let group = owner.create_group("Trip", "EUR", false).await.unwrap();
+        let placeholder = owner
+            .add_placeholder_to_a_group("Bob", group.id)
+            .await
+            .unwrap();
+        let stranger_id = stranger.get_current_user().await.unwrap().unwrap().id;
+
+        assert!(is_not_a_member(stranger.get_ledger(group.id).await));
+        assert!(is_not_a_member(stranger.get_users_in_group(group.id).await));
+        assert!(is_not_a_member(
+            stranger
+                .settle_up(group.id, stranger_id, placeholder.id, Decimal::ONE)
+                .await
+        ));
+        assert!(is_not_a_member(
+            stranger.create_invite(group.id, None, None).await
+        ));
+        assert!(is_not_a_member(
+            stranger.rename_group(group.id, "Mine").await
+        ));
+        assert!(is_not_a_member(stranger.delete_group(group.id).await));
+        assert!(is_not_a_member(
+            stranger
+                .merge_placeholder(group.id, placeholder.id, stranger_id)
+                .await
+        ));
+
+        // Groups that don't exist look just the same
+        assert!(is_not_a_member(stranger.get_ledger(group.id + 1).await));
+    }
+
+    #[tokio::test]
+    async fn members_cant_do_what_only_admins_may() {
+        let bot = Bot::new("token");
+        let db = database().await;
+        let owner = controller(&bot, &db, 1).await;
+        let member = controller(&bot, &db, 2).await;
+        let group = owner.create_group("Trip", "EUR", false).await.unwrap();
+        let placeholder = owner
+            .add_placeholder_to_a_group("Bob", group.id)
+            .await
+            .unwrap();
+        let invite = owner.create_invite(group.id, None, None).await.unwrap();
+        member.join_by_invite(&invite.token).await.unwrap();
+        let member_id = member.get_current_user().await.unwrap().unwrap().id;
+
+        assert!(member.get_ledger(group.id).await.is_ok());
+        assert!(is_not_an_admin(member.rename_group(group.id, "Mine").await));
+        assert!(is_not_an_admin(
+            member.set_group_archived(group.id, true).await
+        ));
+        assert!(is_not_an_admin(member.delete_group(group.id).await));
+        assert!(is_not_an_admin(
+            member.set_group_currency(group.id, "USD").await
+        ));
+        assert!(is_not_an_admin(
+            member
+                .merge_placeholder(group.id, placeholder.id, member_id)
+                .await
+        ));
+    }
+
+    #[tokio::test]
+    async fn those_who_left_a_chat_group_arent_enrolled_again() {
+        let bot = Bot::new("token");
+        let db = database().await;
+        let owner = chat_controller(&bot, &db, 1, -100).await;
+        let member = chat_controller(&bot, &db, 2, -100).await;
+        let group = owner.create_group("Trip", "EUR", true).await.unwrap();
+        let member_id = member.get_current_user().await.unwrap().unwrap().id;
+
+        member.join_chat_group().await.unwrap();
+        assert!(member.get_ledger(group.id).await.is_ok());
+
+        member
+            .remove_member(group.id, member_id, false)
+            .await
+            .unwrap();
+        member.join_chat_group().await.unwrap();
+        assert!(is_not_a_member(member.get_ledger(group.id).await));
+
+        // An invite brings them back, and the chat keeps them in from then on
+        let invite = owner.create_invite(group.id, None, None).await.unwrap();
+        member.join_by_invite(&invite.token).await.unwrap();
+        member.join_chat_group().await.unwrap();
+        assert!(member.get_ledger(group.id).await.is_ok());
+        assert!(!db.has_left_group(group.id, member_id).await.unwrap());
+
+        owner
+            .remove_member(group.id, member_id, false)
+            .await
+            .unwrap();
+        member.join_chat_group().await.unwrap();
+        assert!(is_not_a_member(member.get_ledger(group.id).await));
+    }
+
+    #[tokio::test]
+    async fn expenses_and_payments_name_only_members() {
+        let bot = Bot::new("token");
+        let db = database().await;
+        let owner = controller(&bot, &db, 1).await;
+        let stranger = controller(&bot, &db, 2).await;
+        let group = owner.create_group("Trip", "EUR", false).await.unwrap();
+        let other_group = stranger.create_group("Home", "EUR", false).await.unwrap();
+        let owner_id = owner.get_current_user().await.unwrap().unwrap().id;
+        let stranger_id = stranger.get_current_user().await.unwrap().unwrap().id;
+        let member = owner
+            .add_placeholder_to_a_group("Bob", group.id)
+            .await
+            .unwrap();
+        let outsider = stranger
+            .add_placeholder_to_a_group("Eve", other_group.id)
+            .await
+            .unwrap();
+
+        assert!(is_not_in_group(
+            owner
+                .settle_up(group.id, owner_id, stranger_id, Decimal::ONE)
+                .await
+        ));
+        assert!(is_not_in_group(
+            owner
+                .settle_up(group.id, outsider.id, owner_id, Decimal::ONE)
+                .await
+        ));
+        assert!(is_split_error(
+            owner
+                .settle_up(group.id, owner_id, member.id, Decimal::ZERO)
+                .await,
+            settlement::SplitError::NonPositiveAmount
+        ));
+        assert!(is_split_error(
+            owner
+                .settle_up(group.id, owner_id, member.id, -Decimal::ONE)
+                .await,
+            settlement::SplitError::NonPositiveAmount
+        ));
+        assert!(owner
+            .settle_up(group.id, owner_id, member.id, Decimal::ONE)
+            .await
+            .is_ok());
+
+        let new_expense = db::NewExpense {
+            group_id: group.id,
+            payer: owner_id,
+            payers: vec![],
+            created_by: owner_id,
+            amount: Decimal::TEN,
+            currency: "EUR".to_owned(),
+            exchange_rate: Decimal::ONE,
+            note: "Dinner".to_owned(),
+            split_mode: expense::SplitMode::Equal,
+            participants: vec![(owner_id, None), (member.id, None)],
+        };
+        assert!(owner.add_expense(&new_expense).await.is_ok());
+        assert!(is_not_in_group(
+            owner
+                .add_expense(&db::NewExpense {
+                    payer: outsider.id,
+                    ..new_expense.clone()
+                })
+                .await
+        ));
+        assert!(is_not_in_group(
+            owner
+                .add_expense(&db::NewExpense {
+                    payers: vec![(owner_id, Decimal::ONE), (stranger_id, Decimal::from(9))],
+                    ..new_expense.clone()
+                })
+                .await
+        ));
+        assert!(is_not_in_group(
+            owner
+                .add_expense(&db::NewExpense {
+                    participants: vec![(owner_id, None), (outsider.id, None)],
+                    ..new_expense.clone()
+                })
+                .await
+        ));
+        assert!(is_split_error(
+            owner
+                .add_expense(&db::NewExpense {
+                    amount: -Decimal::TEN,
+                    ..new_expense.clone()
+                })
+                .await,
+            settlement::SplitError::NonPositiveAmount
+        ));
+    }
+
+    #[tokio::test]
+    async fn payments_are_as_precise_as_the_currency() {
+        let bot = Bot::new("token");
+        let db = database().await;
+        let owner = controller(&bot, &db, 1).await;
+        let group = owner.create_group("Trip", "EUR", false).await.unwrap();
+        let owner_id = owner.get_current_user().await.unwrap().unwrap().id;
+        let member = owner
+            .add_placeholder_to_a_group("Bob", group.id)
+            .await
+            .unwrap();
+
+        assert!(is_split_error(
+            owner
+                .settle_up(group.id, member.id, owner_id, Decimal::new(10005, 3))
+                .await,
+            settlement::SplitError::TooManyDecimals {
+                currency: "EUR".to_owned(),
+                decimals: 2
+            }
+        ));
+        assert!(owner
+            .settle_up(group.id, member.id, owner_id, Decimal::new(10050, 3))
+            .await
+            .is_ok());
+    }
+
+    fn is_invite_error<T>(result: anyhow::Result<T>, expected: InviteError) -> bool {
+        result.is_err_and(|err| err.downcast_ref::<InviteError>() == Some(&expected))
+    }
+
+    #[tokio::test]
+    async fn invites_let_people_in_until_they_expire_or_are_used_up() {
+        let bot = Bot::new("token");
+        let db = database().await;
+        let owner = controller(&bot, &db, 1).await;
+        let first = controller(&bot, &db, 2).await;
+        let second = controller(&bot, &db, 3).await;
+        let group = owner.create_group("Trip", "EUR", false).await.unwrap();
+        let owner_id = owner.get_current_user().await.unwrap().unwrap().id;
+
+        assert!(is_invite_error(
+            first.join_by_invite("nonexistent").await,
+            InviteError::Invalid
+        ));
+
+        let expired = db
+            .insert_invite(
+                group.id,
+                owner_id,
+                "expired",
+                Some(chrono::Utc::now() - chrono::Duration::minutes(1)),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(is_invite_error(
+            first.join_by_invite(&expired.token).await,
+            InviteError::Expired
+        ));
+        assert!(is_not_a_member(first.get_ledger(group.id).await));
+
+        let once = owner
+            .create_invite(group.id, Some(chrono::Duration::days(1)), Some(1))
+            .await
+            .unwrap();
+        let (joined, new) = first.join_by_invite(&once.token).await.unwrap();
+        assert_eq!((joined.id, new), (group.id, true));
+        // Members following the link again don't use it up
+        let (_, new) = first.join_by_invite(&once.token).await.unwrap();
+        assert!(!new);
+        assert!(is_invite_error(
+            second.join_by_invite(&once.token).await,
+            InviteError::UsedUp
+        ));
+        assert!(is_not_a_member(second.get_ledger(group.id).await));
+
+        assert!(is_invite_error(
+            owner
+                .create_invite(
+                    group.id,
+                    Some(chrono::Duration::days(i64::from(MAX_INVITE_DAYS) + 1)),
+                    None
+                )
+                .await,
+            InviteError::TooLong
+        ));
+    }
+
+    #[tokio::test]
+    async fn invites_to_archived_groups_are_no_longer_valid() {
+        let bot = Bot::new("token");
+        let db = database().await;
+        let owner = controller(&bot, &db, 1).await;
+        let stranger = controller(&bot, &db, 2).await;
+        let group = owner.create_group("Trip", "EUR", false).await.unwrap();
+        let invite = owner.create_invite(group.id, None, None).await.unwrap();
+
+        owner.set_group_archived(group.id, true).await.unwrap();
+        assert!(is_invite_error(
+            stranger.join_by_invite(&invite.token).await,
+            InviteError::NoLongerValid
+        ));
+        assert!(is_not_a_member(stranger.get_ledger(group.id).await));
+
+        owner.set_group_archived(group.id, false).await.unwrap();
+        assert!(stranger.join_by_invite(&invite.token).await.unwrap().1);
+    }
+
+    fn is_removal_error<T>(result: anyhow::Result<T>, expected: RemovalError) -> bool {
+        result.is_err_and(|err| err.downcast_ref::<RemovalError>() == Some(&expected))
+    }
+
+    #[tokio::test]
+    async fn leaving_owners_hand_the_group_over() {
+        let bot = Bot::new("token");
+        let db = database().await;
+        let owner = controller(&bot, &db, 1).await;
+        let member = controller(&bot, &db, 2).await;
+        let admin = controller(&bot, &db, 3).await;
+        let group = owner.create_group("Trip", "EUR", false).await.unwrap();
+        owner
+            .add_placeholder_to_a_group("Bob", group.id)
+            .await
+            .unwrap();
+        let invite = owner.create_invite(group.id, None, None).await.unwrap();
+        member.join_by_invite(&invite.token).await.unwrap();
+        admin.join_by_invite(&invite.token).await.unwrap();
+        let (owner_id, member_id, admin_id) = (
+            owner.get_current_user().await.unwrap().unwrap().id,
+            member.get_current_user().await.unwrap().unwrap().id,
+            admin.get_current_user().await.unwrap().unwrap().id,
+        );
+        owner
+            .set_member_role(group.id, admin_id, user_group::Role::Admin)
+            .await
+            .unwrap();
+
+        // Admins come before members who have been in the group for longer
+        assert!(is_removal_error(
+            admin.remove_member(group.id, owner_id, false).await,
+            RemovalError::Owner
+        ));
+        owner
+            .remove_member(group.id, owner_id, false)
+            .await
+            .unwrap();
+        let owners = admin
+            .get_members_with_role(group.id, user_group::Role::Owner)
+            .await
+            .unwrap();
+        assert_eq!(owners.iter().map(|x| x.id).collect::<Vec<_>>(), [admin_id]);
+
+        // Placeholders never take a group over
+        admin
+            .remove_member(group.id, admin_id, false)
+            .await
+            .unwrap();
+        let owners = member
+            .get_members_with_role(group.id, user_group::Role::Owner)
+            .await
+            .unwrap();
+        assert_eq!(owners.iter().map(|x| x.id).collect::<Vec<_>>(), [member_id]);
+        assert!(is_removal_error(
+            member.remove_member(group.id, member_id, false).await,
+            RemovalError::NoHeir
+        ));
+    }
+
+    #[tokio::test]
+    async fn members_who_arent_settled_up_stay_unless_forced() {
+        let bot = Bot::new("token");
+        let db = database().await;
+        let owner = controller(&bot, &db, 1).await;
+        let member = controller(&bot, &db, 2).await;
+        let group = owner.create_group("Trip", "EUR", false).await.unwrap();
+        let invite = owner.create_invite(group.id, None, None).await.unwrap();
+        member.join_by_invite(&invite.token).await.unwrap();
+        let owner_id = owner.get_current_user().await.unwrap().unwrap().id;
+        let member_id = member.get_current_user().await.unwrap().unwrap().id;
+
+        owner
+            .add_expense(&db::NewExpense {
+                group_id: group.id,
+                payer: owner_id,
+                payers: vec![],
+                created_by: owner_id,
+                amount: Decimal::TEN,
+                currency: "EUR".to_owned(),
+                exchange_rate: Decimal::ONE,
+                note: "Dinner".to_owned(),
+                split_mode: expense::SplitMode::Equal,
+                participants: vec![(owner_id, None), (member_id, None)],
+            })
+            .await
+            .unwrap();
+
+        assert!(is_removal_error(
+            member.remove_member(group.id, member_id, false).await,
+            RemovalError::NotSettled
+        ));
+        assert!(is_removal_error(
+            owner.remove_member(group.id, member_id, false).await,
+            RemovalError::NotSettled
+        ));
+        assert!(member.get_ledger(group.id).await.is_ok());
+
+        owner
+            .remove_member(group.id, member_id, true)
+            .await
+            .unwrap();
+        assert!(is_not_a_member(member.get_ledger(group.id).await));
+        // Their share is still owed, they are just listed among former members
+        let ledger = owner.get_ledger(group.id).await.unwrap();
+        assert_eq!(
+            ledger
+                .former_members
+                .iter()
+                .map(|x| x.id)
+                .collect::<Vec<_>>(),
+            [member_id]
+        );
+    }
+}
@@ -1,20 +1,31 @@
+use chrono::NaiveDate;
 use rust_decimal::Decimal;
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::NotSet, ColumnTrait, Database as SeaOrmDatabase,
-    DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+    sea_query::{Expr, Func, OnConflict},
+    ActiveModelTrait,
+    ActiveValue::NotSet,
+    ColumnTrait, Condition, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait,
+    QueryFilter, QueryOrder, Set, SqlxSqliteConnector, TransactionTrait,
 };
 use sea_orm_migration::MigratorTrait;
-use std::{fs::OpenOptions, path::PathBuf};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool};
+use std::{collections::HashSet, path::Path};
 
 use crate::{
-    entity::{expense, group, user, user_group},
+    entity::{
+        departure, dialogue, exchange_rate, expense, expense_participant, expense_payer, group,
+        invite, payment, user, user_group,
+    },
     migration::Migrator,
 };
 
 #[derive(Debug)]
 pub enum Error {
     Database(DbErr),
+    Connection(sqlx::Error),
     File(std::io::Error),
+    /// The database was migrated by a newer version of the bot. Contains unknown migrations
+    SchemaTooNew(Vec<String>),
 }
 
 impl std::fmt::Display for Error {
@@ -23,7 +34,14 @@ impl std::fmt::Display for Error {
             Self::Database(ref err) => {
                 write!(f, "Database error: {}", err)
             }
+            Self::Connection(ref err) => write!(f, "Connection error: {}", err),
             Self::File(ref err) => write!(f, "File error: {}", err),
+            Self::SchemaTooNew(ref migrations) => write!(
+                f,
+                "Database schema is newer than this version of splittea supports \
+                 (unknown migrations: {}). Please, upgrade the bot",
+                migrations.join(", ")
+            ),
         }
     }
 }
@@ -34,22 +52,56 @@ impl From<DbErr> for Error {
     }
 }
 
+impl From<sqlx::Error> for Error {
+    fn from(err: sqlx::Error) -> Self {
+        Self::Connection(err)
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
         Self::File(err)
     }
 }
 
-async fn get_db_pool(db_path: &PathBuf) -> Result<DatabaseConnection, Error> {
-    OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(db_path)?;
-    let db_str = format!("sqlite:{}", db_path.display());
-    let pool = SeaOrmDatabase::connect(&db_str).await?;
-    Ok(pool)
+async fn get_db_pool(db_path: &Path) -> Result<DatabaseConnection, Error> {
+    if let Some(dir) = db_path.parent().filter(|x| !x.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    // The file is created only if it doesn't exist yet, so the data survives restarts
+    let options = SqliteConnectOptions::new()
+        .filename(db_path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .foreign_keys(true);
+    let pool = SqlitePool::connect_with(options).await?;
+
+    Ok(SqlxSqliteConnector::from_sqlx_sqlite_pool(pool))
+}
+
+/// Precision of the exchange rates derived from the stored ones
+const RATE_DECIMAL_PLACES: u32 = 6;
+
+/// Expense that is about to be inserted into the database
+#[derive(Clone, Debug)]
+pub struct NewExpense {
+    pub group_id: i64,
+    /// Who actually paid for the expense
+    pub payer: i64,
+    /// Contributions of every payer. Empty means that `payer` paid the whole amount
+    pub payers: Vec<(i64, Decimal)>,
+    /// Who logged the expense into the bot
+    pub created_by: i64,
+    pub amount: Decimal,
+    pub currency: String,
+    /// How much of the group currency one unit of `currency` is worth
+    pub exchange_rate: Decimal,
+    pub note: String,
+    pub split_mode: expense::SplitMode,
+    /// Participants with their shares. There is always at least one, even if the whole group shares
+    /// the expense, so that members who join later aren't charged for it
+    pub participants: Vec<(i64, Option<Decimal>)>,
 }
 
 #[derive(Clone)]
@@ -58,11 +110,50 @@ pub struct Database {
 }
 
 impl Database {
-    pub async fn new(db_path: &PathBuf) -> Result<Self, Error> {
+    pub async fn new(db_path: &Path) -> Result<Self, Error> {
         get_db_pool(db_path).await.map(|pool| Self { pool })
     }
 
+    /// Database that lives only as long as it's used, for tests
+    #[cfg(test)]
+    pub async fn in_memory() -> Result<Self, Error> {
+        // Every connection to `:memory:` opens a database of its own, so the pool has to keep
+        // exactly one connection open the whole time
+        let options = "sqlite::memory:"
+            .parse::<SqliteConnectOptions>()?
+            .foreign_keys(true);
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?;
+
+        Ok(Self {
+            pool: SqlxSqliteConnector::from_sqlx_sqlite_pool(pool),
+        })
+    }
+
+    /// Applies pending migrations. Refuses to touch the database if it contains migrations
+    /// this binary doesn't know about, since they come from a newer version of the bot
     pub async fn apply_migrations(&self) -> Result<(), Error> {
+        let known: HashSet<String> = Migrator::migrations()
+            .iter()
+            .map(|migration| migration.name().to_owned())
+            .collect();
+
+        Migrator::install(&self.pool).await?;
+        let unknown: Vec<String> = Migrator::get_migration_models(&self.pool)
+            .await?
+            .into_iter()
+            .map(|model| model.version)
+            .filter(|version| !known.contains(version))
+            .collect();
+
+        if !unknown.is_empty() {
+            return Err(Error::SchemaTooNew(unknown));
+        }
+
         Ok(Migrator::up(&self.pool, None).await?)
     }
 
@@ -73,31 +164,101 @@ impl Database {
             .filter(user_group::Column::GroupId.eq(group_id))
             .all(&self.pool)
             .await?;
-        let usernames: Vec<String> = user_groups.into_iter().map(|x| x.username).collect();
+        let user_ids: Vec<i64> = user_groups.into_iter().map(|x| x.user_id).collect();
         let users = user::Entity::find()
-            .filter(user::Column::Username.is_in(usernames))
+            .filter(user::Column::Id.is_in(user_ids))
+            .order_by_asc(user::Column::Id)
             .all(&self.pool)
             .await?;
 
         Ok(users)
     }
 
-    pub async fn insert_expense(
-        &self,
-        username: &str,
-        amount: Decimal,
-        group_id: i64,
-        note: &str,
-    ) -> Result<expense::Model, Error> {
+    pub async fn insert_expense(&self, new_expense: &NewExpense) -> Result<expense::Model, Error> {
+        let txn = self.pool.begin().await?;
+        let now = chrono::Utc::now();
+
         let expense = expense::ActiveModel {
             id: NotSet,
-            username: Set(username.to_owned()),
-            group_id: Set(group_id),
-            amount: Set(amount),
-            note: Set(note.to_owned()),
-        };
+            payer: Set(new_expense.payer),
+            created_by: Set(new_expense.created_by),
+            group_id: Set(new_expense.group_id),
+            amount: Set(new_expense.amount),
+            currency: Set(new_expense.currency.clone()),
+            exchange_rate: Set(new_expense.exchange_rate),
+            note: Set(new_expense.note.clone()),
+            split_mode: Set(new_expense.split_mode),
+            created_at: Set(now),
+            updated_at: Set(now),
+        }
+        .insert(&txn)
+        .await?;
+
+        if !new_expense.participants.is_empty() {
+            let participants = new_expense.participants.iter().map(|(user_id, share)| {
+                expense_participant::ActiveModel {
+                    expense_id: Set(expense.id),
+                    user_id: Set(*user_id),
+                    share: Set(*share),
+                }
+            });
+            expense_participant::Entity::insert_many(participants)
+                .exec(&txn)
+                .await?;
+        }
+
+        if !new_expense.payers.is_empty() {
+            let payers =
+                new_expense
+                    .payers
+                    .iter()
+                    .map(|(user_id, amount)| expense_payer::ActiveModel {
+                        expense_id: Set(expense.id),
+                        user_id: Set(*user_id),
+                        amount: Set(*amount),
+                    });
+            expense_payer::Entity::insert_many(payers)
+                .exec(&txn)
+                .await?;
+        }
+
+        txn.commit().await?;
 
-        Ok(expense.insert(&self.pool).await?)
+        Ok(expense)
+    }
+
+    pub async fn get_payers_in_group(
+        &self,
+        group_id: i64,
+    ) -> Result<Vec<expense_payer::Model>, Error> {
+        let expense_ids: Vec<i64> = self
+            .get_expenses_in_group(group_id)
+            .await?
+            .into_iter()
+            .map(|x| x.id)
+            .collect();
+
+        Ok(expense_payer::Entity::find()
+            .filter(expense_payer::Column::ExpenseId.is_in(expense_ids))
+            .all(&self.pool)
+            .await?)
+    }
+
+    pub async fn get_participants_in_group(
+        &self,
+        group_id: i64,
+    ) -> Result<Vec<expense_participant::Model>, Error> {
+        let expense_ids: Vec<i64> = self
+            .get_expenses_in_group(group_id)
+            .await?
+            .into_iter()
+            .map(|x| x.id)
+            .collect();
+
+        Ok(expense_participant::Entity::find()
+            .filter(expense_participant::Column::ExpenseId.is_in(expense_ids))
+            .all(&self.pool)
+            .await?)
     }
 
     pub async fn get_expenses_in_group(&self, group_id: i64) -> Result<Vec<expense::Model>, Error> {
@@ -107,15 +268,306 @@ impl Database {
             .await?)
     }
 
+    pub async fn get_expense_by_id(
+        &self,
+        expense_id: i64,
+    ) -> Result<Option<expense::Model>, Error> {
+        Ok(expense::Entity::find_by_id(expense_id)
+            .one(&self.pool)
+            .await?)
+    }
+
+    pub async fn get_expense_participants(
+        &self,
+        expense_id: i64,
+    ) -> Result<Vec<expense_participant::Model>, Error> {
+        Ok(expense_participant::Entity::find()
+            .filter(expense_participant::Column::ExpenseId.eq(expense_id))
+            .all(&self.pool)
+            .await?)
+    }
+
+    pub async fn get_expense_payers(
+        &self,
+        expense_id: i64,
+    ) -> Result<Vec<expense_payer::Model>, Error> {
+        Ok(expense_payer::Entity::find()
+            .filter(expense_payer::Column::ExpenseId.eq(expense_id))
+            .all(&self.pool)
+            .await?)
+    }
+
+    pub async fn update_expense(
+        &self,
+        mut expense: expense::ActiveModel,
+    ) -> Result<expense::Model, Error> {
+        expense.updated_at = Set(chrono::Utc::now());
+        Ok(expense.update(&self.pool).await?)
+    }
+
+    /// Replaces participants of an expense together with the way it's split among them
+    pub async fn replace_expense_participants(
+        &self,
+        expense: expense::Model,
+        split_mode: expense::SplitMode,
+        participants: &[(i64, Option<Decimal>)],
+    ) -> Result<expense::Model, Error> {
+        let txn = self.pool.begin().await?;
+
+        expense_participant::Entity::delete_many()
+            .filter(expense_participant::Column::ExpenseId.eq(expense.id))
+            .exec(&txn)
+            .await?;
+
+        if !participants.is_empty() {
+            let participants =
+                participants
+                    .iter()
+                    .map(|(user_id, share)| expense_participant::ActiveModel {
+                        expense_id: Set(expense.id),
+                        user_id: Set(*user_id),
+                        share: Set(*share),
+                    });
+            expense_participant::Entity::insert_many(participants)
+                .exec(&txn)
+                .await?;
+        }
+
+        let mut expense: expense::ActiveModel = expense.into();
+        expense.split_mode = Set(split_mode);
+        expense.updated_at = Set(chrono::Utc::now());
+        let expense = expense.update(&txn).await?;
+
+        txn.commit().await?;
+
+        Ok(expense)
+    }
+
+    /// Participants and payers of the expense are removed with it by the foreign keys
+    pub async fn delete_expense(&self, expense_id: i64) -> Result<(), Error> {
+        expense::Entity::delete_by_id(expense_id)
+            .exec(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Records several payments at once, e.g. all suggested transfers of a group
+    pub async fn insert_payments(
+        &self,
+        group_id: i64,
+        currency: &str,
+        payments: &[(i64, i64, Decimal)],
+    ) -> Result<Vec<payment::Model>, Error> {
+        let txn = self.pool.begin().await?;
+        let created_at = chrono::Utc::now();
+
+        let mut inserted = Vec::new();
+        for (from_user, to_user, amount) in payments {
+            let payment = payment::ActiveModel {
+                id: NotSet,
+                group_id: Set(group_id),
+                from_user: Set(*from_user),
+                to_user: Set(*to_user),
+                amount: Set(*amount),
+                currency: Set(currency.to_owned()),
+                created_at: Set(created_at),
+            };
+            inserted.push(payment.insert(&txn).await?);
+        }
+
+        txn.commit().await?;
+
+        Ok(inserted)
+    }
+
+    pub async fn get_payments_in_group(&self, group_id: i64) -> Result<Vec<payment::Model>, Error> {
+        Ok(payment::Entity::find()
+            .filter(payment::Column::GroupId.eq(group_id))
+            .all(&self.pool)
+            .await?)
+    }
+
+    pub async fn get_payment_by_id(
+        &self,
+        payment_id: i64,
+    ) -> Result<Option<payment::Model>, Error> {
+        Ok(payment::Entity::find_by_id(payment_id)
+            .one(&self.pool)
+            .await?)
+    }
+
+    pub async fn delete_payment(&self, payment_id: i64) -> Result<(), Error> {
+        payment::Entity::delete_by_id(payment_id)
+            .exec(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Stores exchange rates, replacing the ones already known for the same day and currencies
+    pub async fn upsert_exchange_rates(&self, rates: &[exchange_rate::Model]) -> Result<(), Error> {
+        let txn = self.pool.begin().await?;
+
+        // SQLite limits the number of variables in a single statement
+        for chunk in rates.chunks(200) {
+            let chunk = chunk
+                .iter()
+                .map(|x| exchange_rate::ActiveModel::from(x.clone()));
+            exchange_rate::Entity::insert_many(chunk)
+                .on_conflict(
+                    OnConflict::columns([
+                        exchange_rate::Column::Date,
+                        exchange_rate::Column::FromCurrency,
+                        exchange_rate::Column::ToCurrency,
+                    ])
+                    .update_column(exchange_rate::Column::Rate)
+                    .to_owned(),
+                )
+                .exec(&txn)
+                .await?;
+        }
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    /// The latest rate known on or before `date`. If there is no such rate, it's derived from the inverse
+    /// one or crossed through a currency both are quoted against, e.g. USD to GBP through EUR
+    pub async fn find_exchange_rate(
+        &self,
+        date: NaiveDate,
+        from: &str,
+        to: &str,
+    ) -> Result<Option<exchange_rate::Model>, Error> {
+        let latest = |from: &str, to: &str| {
+            exchange_rate::Entity::find()
+                .filter(exchange_rate::Column::FromCurrency.eq(from))
+                .filter(exchange_rate::Column::ToCurrency.eq(to))
+                .filter(exchange_rate::Column::Date.lte(date))
+                .order_by_desc(exchange_rate::Column::Date)
+        };
+
+        if let Some(direct) = latest(from, to).one(&self.pool).await? {
+            return Ok(Some(direct));
+        }
+
+        // Rates too extreme to be inverted or crossed are as good as unknown
+        if let Some(inverse) = latest(to, from).one(&self.pool).await? {
+            return Ok(Decimal::ONE
+                .checked_div(inverse.rate)
+                .map(|rate| exchange_rate::Model {
+                    from_currency: from.to_owned(),
+                    to_currency: to.to_owned(),
+                    rate: rate.round_dp(RATE_DECIMAL_PLACES),
+                    ..inverse
+                }));
+        }
+
+        let base = exchange_rate::Entity::find()
+            .filter(exchange_rate::Column::ToCurrency.eq(from))
+            .filter(exchange_rate::Column::Date.lte(date))
+            .order_by_desc(exchange_rate::Column::Date)
+            .one(&self.pool)
+            .await?;
+        let Some(base) = base else {
+            return Ok(None);
+        };
+
+        let cross = exchange_rate::Entity::find_by_id((
+            base.date,
+            base.from_currency.clone(),
+            to.to_owned(),
+        ))
+        .one(&self.pool)
+        .await?;
+
+        Ok(cross
+            .and_then(|cross| cross.rate.checked_div(base.rate))
+            .map(|rate| exchange_rate::Model {
+                date: base.date,
+                from_currency: from.to_owned(),
+                to_currency: to.to_owned(),
+                rate: rate.round_dp(RATE_DECIMAL_PLACES),
+            }))
+    }
+
+    pub async fn get_dialogue(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+    ) -> Result<Option<dialogue::Model>, Error> {
+        Ok(dialogue::Entity::find_by_id((chat_id, user_id))
+            .one(&self.pool)
+            .await?)
+    }
+
+    /// Stores the serialized state of a dialogue, replacing the previous one
+    pub async fn upsert_dialogue(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+        state: String,
+    ) -> Result<(), Error> {
+        let dialogue = dialogue::ActiveModel {
+            chat_id: Set(chat_id),
+            user_id: Set(user_id),
+            state: Set(state),
+            updated_at: Set(chrono::Utc::now()),
+        };
+        dialogue::Entity::insert(dialogue)
+            .on_conflict(
+                OnConflict::columns([dialogue::Column::ChatId, dialogue::Column::UserId])
+                    .update_columns([dialogue::Column::State, dialogue::Column::UpdatedAt])
+                    .to_owned(),
+            )
+            .exec(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_dialogue(&self, chat_id: i64, user_id: i64) -> Result<(), Error> {
+        dialogue::Entity::delete_by_id((chat_id, user_id))
+            .exec(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Removes dialogues nobody has touched since `before`. Returns how many were removed
+    pub async fn remove_stale_dialogues(
+        &self,
+        before: chrono::DateTime<chrono::Utc>,
+    ) -> Result<u64, Error> {
+        let result = dialogue::Entity::delete_many()
+            .filter(dialogue::Column::UpdatedAt.lt(before))
+            .exec(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected)
+    }
+
     #[allow(unused)]
     pub async fn remove_migrations(&self) -> Result<(), Error> {
         Ok(Migrator::down(&self.pool, None).await?)
     }
 
-    pub async fn insert_group(&self, group: &str) -> Result<group::Model, Error> {
+    pub async fn insert_group(
+        &self,
+        group: &str,
+        currency: &str,
+        chat_id: Option<i64>,
+    ) -> Result<group::Model, Error> {
+        let now = chrono::Utc::now();
         let group = group::ActiveModel {
             id: NotSet,
             name: Set(group.to_string()),
+            currency: Set(currency.to_owned()),
+            simplification: Set(group::Simplification::default()),
+            chat_id: Set(chat_id),
+            archived_at: Set(None),
+            created_at: Set(now),
+            updated_at: Set(now),
         };
         Ok(group.insert(&self.pool).await?)
     }
@@ -127,21 +579,87 @@ impl Database {
             .await?)
     }
 
-    pub async fn add_user_to_group(&self, group_id: i64, username: &str) -> Result<(), Error> {
-        let user = user::ActiveModel {
-            username: Set(username.to_string()),
-        };
+    pub async fn get_group_by_chat(&self, chat_id: i64) -> Result<Option<group::Model>, Error> {
+        Ok(group::Entity::find()
+            .filter(group::Column::ChatId.eq(chat_id))
+            .one(&self.pool)
+            .await?)
+    }
+
+    /// Changes the currency of a group. Expenses and payments keep the currency they were made in
+    pub async fn set_group_currency(
+        &self,
+        group: group::Model,
+        currency: &str,
+    ) -> Result<group::Model, Error> {
+        let mut group: group::ActiveModel = group.into();
+        group.currency = Set(currency.to_owned());
+        group.updated_at = Set(chrono::Utc::now());
+        Ok(group.update(&self.pool).await?)
+    }
+
+    pub async fn set_group_simplification(
+        &self,
+        group: group::Model,
+        simplification: group::Simplification,
+    ) -> Result<group::Model, Error> {
+        let mut group: group::ActiveModel = group.into();
+        group.simplification = Set(simplification);
+        group.updated_at = Set(chrono::Utc::now());
+        Ok(group.update(&self.pool).await?)
+    }
+
+    pub async fn rename_group(
+        &self,
+        group: group::Model,
+        name: &str,
+    ) -> Result<group::Model, Error> {
+        let mut group: group::ActiveModel = group.into();
+        group.name = Set(name.to_owned());
+        group.updated_at = Set(chrono::Utc::now());
+        Ok(group.update(&self.pool).await?)
+    }
+
+    pub async fn set_group_archived(
+        &self,
+        group: group::Model,
+        archived: bool,
+    ) -> Result<group::Model, Error> {
+        let now = chrono::Utc::now();
+        let mut group: group::ActiveModel = group.into();
+        group.archived_at = Set(archived.then_some(now));
+        group.updated_at = Set(now);
+        Ok(group.update(&self.pool).await?)
+    }
+
+    /// Memberships, expenses and payments of the group are removed with it by the foreign keys
+    pub async fn delete_group(&self, group_id: i64) -> Result<(), Error> {
+        group::Entity::delete_by_id(group_id)
+            .exec(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Being added to a group again clears any record of having left it
+    pub async fn add_user_to_group(
+        &self,
+        group_id: i64,
+        user_id: i64,
+        role: user_group::Role,
+    ) -> Result<(), Error> {
+        departure::Entity::delete_by_id((user_id, group_id))
+            .exec(&self.pool)
+            .await?;
 
+        let now = chrono::Utc::now();
         let user_group = user_group::ActiveModel {
-            username: Set(username.to_string()),
+            user_id: Set(user_id),
             group_id: Set(group_id),
+            role: Set(role),
+            created_at: Set(now),
+            updated_at: Set(now),
         };
 
-        // TODO: This is a bit awkward and better be rewritten with `save()` commands
-        if let Err(err) = user.insert(&self.pool).await {
-            tracing::error!(?err, "Error occurred during `user` insertion");
-        }
-
         if let Err(err) = user_group.insert(&self.pool).await {
             tracing::error!(?err, "Error occurred during `user_group` insertion");
         }
@@ -149,12 +667,162 @@ impl Database {
         Ok(())
     }
 
+    /// Removes a user from a group, keeping every expense and payment they've taken part in, and
+    /// records the departure. `new_owner` takes the group over, if given
+    pub async fn remove_user_from_group(
+        &self,
+        group_id: i64,
+        user_id: i64,
+        new_owner: Option<i64>,
+    ) -> Result<(), Error> {
+        let txn = self.pool.begin().await?;
+
+        user_group::Entity::delete_by_id((user_id, group_id))
+            .exec(&txn)
+            .await?;
+
+        departure::Entity::insert(departure::ActiveModel {
+            user_id: Set(user_id),
+            group_id: Set(group_id),
+            created_at: Set(chrono::Utc::now()),
+        })
+        .on_conflict(
+            OnConflict::columns([departure::Column::UserId, departure::Column::GroupId])
+                .update_column(departure::Column::CreatedAt)
+                .to_owned(),
+        )
+        .exec(&txn)
+        .await?;
+
+        if let Some(new_owner) = new_owner {
+            user_group::ActiveModel {
+                user_id: Set(new_owner),
+                group_id: Set(group_id),
+                role: Set(user_group::Role::Owner),
+                updated_at: Set(chrono::Utc::now()),
+                ..Default::default()
+            }
+            .update(&txn)
+            .await?;
+        }
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    /// Whether the user left the group or was removed from it and hasn't been added back since
+    pub async fn has_left_group(&self, group_id: i64, user_id: i64) -> Result<bool, Error> {
+        Ok(departure::Entity::find_by_id((user_id, group_id))
+            .one(&self.pool)
+            .await?
+            .is_some())
+    }
+
+    pub async fn get_membership(
+        &self,
+        group_id: i64,
+        user_id: i64,
+    ) -> Result<Option<user_group::Model>, Error> {
+        Ok(user_group::Entity::find_by_id((user_id, group_id))
+            .one(&self.pool)
+            .await?)
+    }
+
+    pub async fn get_memberships_in_group(
+        &self,
+        group_id: i64,
+    ) -> Result<Vec<user_group::Model>, Error> {
+        Ok(user_group::Entity::find()
+            .filter(user_group::Column::GroupId.eq(group_id))
+            .all(&self.pool)
+            .await?)
+    }
+
+    pub async fn set_member_role(
+        &self,
+        membership: user_group::Model,
+        role: user_group::Role,
+    ) -> Result<user_group::Model, Error> {
+        let mut membership: user_group::ActiveModel = membership.into();
+        membership.role = Set(role);
+        membership.updated_at = Set(chrono::Utc::now());
+        Ok(membership.update(&self.pool).await?)
+    }
+
+    pub async fn insert_invite(
+        &self,
+        group_id: i64,
+        created_by: i64,
+        token: &str,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+        max_uses: Option<i32>,
+    ) -> Result<invite::Model, Error> {
+        let invite = invite::ActiveModel {
+            id: NotSet,
+            token: Set(token.to_owned()),
+            group_id: Set(group_id),
+            created_by: Set(created_by),
+            expires_at: Set(expires_at),
+            max_uses: Set(max_uses),
+            uses: Set(0),
+            created_at: Set(chrono::Utc::now()),
+        };
+        Ok(invite.insert(&self.pool).await?)
+    }
+
+    pub async fn get_invite_by_token(&self, token: &str) -> Result<Option<invite::Model>, Error> {
+        Ok(invite::Entity::find()
+            .filter(invite::Column::Token.eq(token))
+            .one(&self.pool)
+            .await?)
+    }
+
+    /// Counts a use of the invite and makes the user a member of its group. Returns `false` without
+    /// doing anything if the invite has been used up in the meantime
+    pub async fn redeem_invite(&self, invite: &invite::Model, user_id: i64) -> Result<bool, Error> {
+        let txn = self.pool.begin().await?;
+
+        let counted = invite::Entity::update_many()
+            .col_expr(invite::Column::Uses, Expr::col(invite::Column::Uses).add(1))
+            .filter(invite::Column::Id.eq(invite.id))
+            .filter(
+                Condition::any()
+                    .add(invite::Column::MaxUses.is_null())
+                    .add(Expr::col(invite::Column::Uses).lt(Expr::col(invite::Column::MaxUses))),
+            )
+            .exec(&txn)
+            .await?;
+        if counted.rows_affected == 0 {
+            return Ok(false);
+        }
+
+        departure::Entity::delete_by_id((user_id, invite.group_id))
+            .exec(&txn)
+            .await?;
+
+        let now = chrono::Utc::now();
+        user_group::ActiveModel {
+            user_id: Set(user_id),
+            group_id: Set(invite.group_id),
+            role: Set(user_group::Role::Member),
+            created_at: Set(now),
+            updated_at: Set(now),
+        }
+        .insert(&txn)
+        .await?;
+
+        txn.commit().await?;
+
+        Ok(true)
+    }
+
     pub async fn get_user_groups(
         &self,
-        username: &str,
+        user_id: i64,
     ) -> Result<std::vec::Vec<group::Model>, Error> {
         let user_groups_ids: Vec<i64> = user_group::Entity::find()
-            .filter(user_group::Column::Username.eq(username))
+            .filter(user_group::Column::UserId.eq(user_id))
             .all(&self.pool)
             .await?
             .into_iter()
@@ -168,4 +836,494 @@ impl Database {
 
         Ok(groups)
     }
+
+    pub async fn get_user_by_id(&self, user_id: i64) -> Result<Option<user::Model>, Error> {
+        Ok(user::Entity::find_by_id(user_id).one(&self.pool).await?)
+    }
+
+    pub async fn get_users_by_ids(&self, user_ids: &[i64]) -> Result<Vec<user::Model>, Error> {
+        Ok(user::Entity::find()
+            .filter(user::Column::Id.is_in(user_ids.iter().copied()))
+            .all(&self.pool)
+            .await?)
+    }
+
+    pub async fn get_user_by_telegram_id(
+        &self,
+        telegram_id: i64,
+    ) -> Result<Option<user::Model>, Error> {
+        Ok(user::Entity::find()
+            .filter(user::Column::TelegramId.eq(telegram_id))
+            .one(&self.pool)
+            .await?)
+    }
+
+    /// Finds a user by @username ignoring the case. Users who talked to the bot win over placeholders
+    pub async fn find_user_by_username(
+        &self,
+        username: &str,
+    ) -> Result<Option<user::Model>, Error> {
+        let users = user::Entity::find()
+            .filter(
+                Expr::expr(Func::lower(Expr::col(user::Column::Username)))
+                    .eq(username.to_lowercase()),
+            )
+            .all(&self.pool)
+            .await?;
+
+        Ok(users.into_iter().max_by_key(|x| x.telegram_id.is_some()))
+    }
+
+    /// Returns the user known by @username or creates a placeholder, which is claimed by the real
+    /// user once they talk to the bot
+    pub async fn get_or_insert_user_by_username(
+        &self,
+        username: &str,
+    ) -> Result<user::Model, Error> {
+        if let Some(user) = self.find_user_by_username(username).await? {
+            return Ok(user);
+        }
+
+        let user = user::ActiveModel {
+            id: NotSet,
+            telegram_id: Set(None),
+            username: Set(Some(username.to_owned())),
+            display_name: Set(username.to_owned()),
+        };
+        Ok(user.insert(&self.pool).await?)
+    }
+
+    /// Adds a named member who isn't on Telegram
+    pub async fn insert_placeholder_user(&self, name: &str) -> Result<user::Model, Error> {
+        let user = user::ActiveModel {
+            id: NotSet,
+            telegram_id: Set(None),
+            username: Set(None),
+            display_name: Set(name.to_owned()),
+        };
+        Ok(user.insert(&self.pool).await?)
+    }
+
+    /// Hands memberships, expenses and payments of user `from` over to user `into` and removes `from`
+    pub async fn merge_users(&self, from: i64, into: i64) -> Result<(), Error> {
+        let txn = self.pool.begin().await?;
+        merge_users(&txn, from, into).await?;
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    /// Registers a Telegram user or refreshes their @username and name. A placeholder that was added
+    /// by the same @username before is claimed, or merged if the user is already registered
+    pub async fn sync_user(
+        &self,
+        telegram_id: i64,
+        username: Option<&str>,
+        display_name: &str,
+    ) -> Result<user::Model, Error> {
+        let txn = self.pool.begin().await?;
+
+        let registered = user::Entity::find()
+            .filter(user::Column::TelegramId.eq(telegram_id))
+            .one(&txn)
+            .await?;
+
+        let mut placeholder = None;
+        if let Some(username) = username {
+            let same_username = user::Entity::find()
+                .filter(
+                    Expr::expr(Func::lower(Expr::col(user::Column::Username)))
+                        .eq(username.to_lowercase()),
+                )
+                .all(&txn)
+                .await?;
+
+            for other in same_username {
+                if Some(other.id) == registered.as_ref().map(|x| x.id) {
+                    continue;
+                }
+
+                match (other.telegram_id, &placeholder) {
+                    (None, None) => placeholder = Some(other),
+                    (None, Some(kept)) => merge_users(&txn, other.id, kept.id).await?,
+                    // The @username belonged to somebody else, who has changed it since then
+                    (Some(_), _) => {
+                        let mut other: user::ActiveModel = other.into();
+                        other.username = Set(None);
+                        other.update(&txn).await?;
+                    }
+                }
+            }
+        }
+
+        let user = match (registered, placeholder) {
+            (Some(registered), Some(placeholder)) => {
+                merge_users(&txn, placeholder.id, registered.id).await?;
+                registered
+            }
+            (Some(registered), None) => registered,
+            (None, Some(placeholder)) => placeholder,
+            (None, None) => {
+                user::ActiveModel {
+                    id: NotSet,
+                    telegram_id: Set(Some(telegram_id)),
+                    username: Set(username.map(str::to_owned)),
+                    display_name: Set(display_name.to_owned()),
+                }
+                .insert(&txn)
+                .await?
+            }
+        };
+
+        let user = if user.telegram_id != Some(telegram_id)
+            || user.username.as_deref() != username
+            || user.display_name != display_name
+        {
+            let mut user: user::ActiveModel = user.into();
+            user.telegram_id = Set(Some(telegram_id));
+            user.username = Set(username.map(str::to_owned));
+            user.display_name = Set(display_name.to_owned());
+            user.update(&txn).await?
+        } else {
+            user
+        };
+
+        txn.commit().await?;
+
+        Ok(user)
+    }
+}
+
+/// Moves everything that references user `from` to user `into` and removes `from`.
+/// When both took part in the same expense their shares and contributions are combined
+async fn merge_users(txn: &DatabaseTransaction, from: i64, into: i64) -> Result<(), Error> {
+    let memberships = user_group::Entity::find()
+        .filter(user_group::Column::UserId.eq(from))
+        .all(txn)
+        .await?;
+    for membership in memberships {
+        let existing = user_group::Entity::find_by_id((into, membership.group_id))
+            .one(txn)
+            .await?;
+        match existing {
+            // Rights given to either of them stay with the merged user
+            Some(existing)
+                if membership.role.is_admin() && existing.role == user_group::Role::Member =>
+            {
+                let mut existing: user_group::ActiveModel = existing.into();
+                existing.role = Set(membership.role);
+                existing.update(txn).await?;
+            }
+            Some(_) => {}
+            None => {
+                user_group::ActiveModel {
+                    user_id: Set(into),
+                    ..membership.into()
+                }
+                .insert(txn)
+                .await?;
+            }
+        }
+    }
+
+    let participants = expense_participant::Entity::find()
+        .filter(expense_participant::Column::UserId.eq(from))
+        .all(txn)
+        .await?;
+    for participant in participants {
+        let existing = expense_participant::Entity::find_by_id((participant.expense_id, into))
+            .one(txn)
+            .await?;
+        match existing {
+            Some(existing) => {
+                let share = existing.share.zip(participant.share).map(|(a, b)| a + b);
+                let mut existing: expense_participant::ActiveModel = existing.into();
+                existing.share = Set(share);
+                existing.update(txn).await?;
+            }
+            None => {
+                expense_participant::ActiveModel {
+                    user_id: Set(into),
+                    ..participant.into()
+                }
+                .insert(txn)
+                .await?;
+            }
+        }
+    }
+
+    let payers = expense_payer::Entity::find()
+        .filter(expense_payer::Column::UserId.eq(from))
+        .all(txn)
+        .await?;
+    for payer in payers {
+        let existing = expense_payer::Entity::find_by_id((payer.expense_id, into))
+            .one(txn)
+            .await?;
+        match existing {
+            Some(existing) => {
+                let amount = existing.amount + payer.amount;
+                let mut existing: expense_payer::ActiveModel = existing.into();
+                existing.amount = Set(amount);
+                existing.update(txn).await?;
+            }
+            None => {
+                expense_payer::ActiveModel {
+                    user_id: Set(into),
+                    ..payer.into()
+                }
+                .insert(txn)
+                .await?;
+            }
+        }
+    }
+
+    expense::Entity::update_many()
+        .col_expr(expense::Column::Payer, Expr::value(into))
+        .filter(expense::Column::Payer.eq(from))
+        .exec(txn)
+        .await?;
+    expense::Entity::update_many()
+        .col_expr(expense::Column::CreatedBy, Expr::value(into))
+        .filter(expense::Column::CreatedBy.eq(from))
+        .exec(txn)
+        .await?;
+    payment::Entity::update_many()
+        .col_expr(payment::Column::FromUser, Expr::value(into))
+        .filter(payment::Column::FromUser.eq(from))
+        .exec(txn)
+        .await?;
+    payment::Entity::update_many()
+        .col_expr(payment::Column::ToUser, Expr::value(into))
+        .filter(payment::Column::ToUser.eq(from))
+        .exec(txn)
+        .await?;
+
+    // Memberships, shares and contributions that are still left are removed by the foreign keys
+    user::Entity::delete_by_id(from).exec(txn).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::ConnectionTrait;
+
+    /// Migrated database of its own, so that tests don't share any state
+    async fn database() -> Database {
+        let db = Database::in_memory().await.unwrap();
+        db.apply_migrations().await.unwrap();
+        db
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    fn rate(day: &str, from: &str, to: &str, rate: Decimal) -> exchange_rate::Model {
+        exchange_rate::Model {
+            date: date(day),
+            from_currency: from.to_owned(),
+            to_currency: to.to_owned(),
+            rate,
+        }
+    }
+
+    #[tokio::test]
+    async fn exchange_rates_are_found_directly_inverted_or_crossed() {
+        let db = database().await;
+        db.upsert_exchange_rates(&[
+            rate("2024-03-14", "EUR", "USD", Decimal::new(10925, 4)),
+            rate("2024-03-15", "EUR", "USD", Decimal::new(10892, 4)),
+            rate("2024-03-15", "EUR", "JPY", Decimal::new(16167, 2)),
+        ])
+        .await
+        .unwrap();
+        let find = |day, from, to| db.find_exchange_rate(date(day), from, to);
+
+        // The latest rate on or before the day is used
+        assert_eq!(
+            find("2024-03-20", "EUR", "USD").await.unwrap(),
+            Some(rate("2024-03-15", "EUR", "USD", Decimal::new(10892, 4)))
+        );
+        assert_eq!(
+            find("2024-03-14", "EUR", "USD").await.unwrap(),
+            Some(rate("2024-03-14", "EUR", "USD", Decimal::new(10925, 4)))
+        );
+        assert_eq!(find("2024-03-13", "EUR", "USD").await.unwrap(), None);
+
+        assert_eq!(
+            find("2024-03-15", "USD", "EUR").await.unwrap(),
+            Some(rate("2024-03-15", "USD", "EUR", Decimal::new(918105, 6)))
+        );
+        assert_eq!(
+            find("2024-03-15", "USD", "JPY").await.unwrap(),
+            Some(rate("2024-03-15", "USD", "JPY", Decimal::new(148430040, 6)))
+        );
+
+        // JPY has no rate on the 14th, so there is nothing to cross with
+        assert_eq!(find("2024-03-14", "USD", "JPY").await.unwrap(), None);
+        assert_eq!(find("2024-03-15", "GBP", "EUR").await.unwrap(), None);
+    }
+
+    /// Rows as the bot stored them before users were keyed by id, i.e. by their @username
+    const USERS_BY_USERNAME: &[&str] = &[
+        r#"INSERT INTO "user" ("username") VALUES ('@alice'), ('@bob'), ('@carol')"#,
+        r#"INSERT INTO "group" ("id", "name", "created_at", "updated_at")
+            VALUES (1, 'Trip', '2024-06-01T10:00:00Z', '2024-06-01T10:00:00Z')"#,
+        r#"INSERT INTO "user_group" ("username", "group_id", "created_at", "updated_at") VALUES
+            ('@alice', 1, '2024-06-01T10:00:00Z', '2024-06-01T10:00:00Z'),
+            ('@bob', 1, '2024-06-02T10:00:00Z', '2024-06-02T10:00:00Z')"#,
+        r#"INSERT INTO "expense"
+            ("id", "note", "amount", "payer", "created_by", "group_id", "split_mode", "created_at", "updated_at")
+            VALUES
+            (1, 'Dinner', 30, '@alice', '@alice', 1, 'equal', '2024-06-03T10:00:00Z', '2024-06-03T10:00:00Z'),
+            (2, 'Taxi', 12, '@alice', '@bob', 1, 'exact', '2024-06-04T10:00:00Z', '2024-06-04T10:00:00Z')"#,
+        r#"INSERT INTO "expense_participant" ("expense_id", "username", "share") VALUES
+            (1, '@alice', NULL), (1, '@bob', NULL), (2, '@bob', 12)"#,
+        r#"INSERT INTO "expense_payer" ("expense_id", "username", "amount") VALUES
+            (2, '@alice', 5), (2, '@bob', 7)"#,
+        r#"INSERT INTO "payment" ("id", "group_id", "from_user", "to_user", "amount", "created_at")
+            VALUES (1, 1, '@bob', '@alice', 10, '2024-06-05T10:00:00Z')"#,
+    ];
+
+    #[tokio::test]
+    async fn users_keyed_by_username_are_keyed_by_id() {
+        let db = Database::in_memory().await.unwrap();
+        // Up to `m20240620_000007_add_timestamps`, the last migration before users got ids
+        Migrator::up(&db.pool, Some(7)).await.unwrap();
+        for statement in USERS_BY_USERNAME {
+            db.pool.execute_unprepared(statement).await.unwrap();
+        }
+        db.apply_migrations().await.unwrap();
+
+        // Nobody has talked to the bot since, so they are all placeholders named after their @username
+        let mut ids = Vec::new();
+        for username in ["@alice", "@bob", "@carol"] {
+            let user = db.find_user_by_username(username).await.unwrap().unwrap();
+            assert_eq!(user.telegram_id, None);
+            assert_eq!(user.display_name, username);
+            ids.push(user.id);
+        }
+        let (alice, bob, carol) = (ids[0], ids[1], ids[2]);
+
+        let members: Vec<i64> = db
+            .get_users_in_group(1)
+            .await
+            .unwrap()
+            .iter()
+            .map(|x| x.id)
+            .collect();
+        assert_eq!(members, vec![alice, bob]);
+        assert!(!members.contains(&carol));
+
+        let expenses = db.get_expenses_in_group(1).await.unwrap();
+        assert_eq!(expenses.len(), 2);
+        assert_eq!((expenses[0].payer, expenses[0].created_by), (alice, alice));
+        assert_eq!((expenses[1].payer, expenses[1].created_by), (alice, bob));
+        assert_eq!(expenses[0].amount, Decimal::from(30));
+
+        let mut participants = Vec::new();
+        for expense_id in [1, 2] {
+            let shares: Vec<(i64, Option<Decimal>)> = db
+                .get_expense_participants(expense_id)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|x| (x.user_id, x.share))
+                .collect();
+            participants.push(shares);
+        }
+        assert_eq!(
+            participants,
+            vec![
+                vec![(alice, None), (bob, None)],
+                vec![(bob, Some(Decimal::from(12)))]
+            ]
+        );
+
+        let payers: Vec<(i64, Decimal)> = db
+            .get_expense_payers(2)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.user_id, x.amount))
+            .collect();
+        assert_eq!(
+            payers,
+            vec![(alice, Decimal::from(5)), (bob, Decimal::from(7))]
+        );
+
+        let payments = db.get_payments_in_group(1).await.unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!((payments[0].from_user, payments[0].to_user), (bob, alice));
+        assert_eq!(payments[0].amount, Decimal::from(10));
+    }
+
+    #[tokio::test]
+    async fn users_claim_placeholders_and_take_over_usernames() {
+        let db = database().await;
+        let group = db.insert_group("Trip", "EUR", None).await.unwrap();
+
+        // A placeholder added by @username is claimed by whoever talks to the bot with it
+        let placeholder = db.get_or_insert_user_by_username("@alice").await.unwrap();
+        db.add_user_to_group(group.id, placeholder.id, user_group::Role::Member)
+            .await
+            .unwrap();
+        let alice = db.sync_user(100, Some("@Alice"), "Alice").await.unwrap();
+        assert_eq!(alice.id, placeholder.id);
+        assert_eq!(alice.telegram_id, Some(100));
+        assert_eq!(alice.username.as_deref(), Some("@Alice"));
+        assert_eq!(alice.display_name, "Alice");
+        assert!(db
+            .get_membership(group.id, alice.id)
+            .await
+            .unwrap()
+            .is_some());
+
+        // Syncing again changes nothing
+        assert_eq!(
+            db.sync_user(100, Some("@Alice"), "Alice").await.unwrap(),
+            alice
+        );
+
+        // Once somebody else shows up with the @username, the one who had it has changed it since
+        let bob = db.sync_user(200, Some("@bob"), "Bob").await.unwrap();
+        let new_bob = db.sync_user(300, Some("@bob"), "Robert").await.unwrap();
+        assert_ne!(new_bob.id, bob.id);
+        assert_eq!(new_bob.username.as_deref(), Some("@bob"));
+        let old_bob = db.get_user_by_id(bob.id).await.unwrap().unwrap();
+        assert_eq!(old_bob.username, None);
+        assert_eq!(old_bob.telegram_id, Some(200));
+        assert_eq!(
+            db.find_user_by_username("@bob").await.unwrap().unwrap().id,
+            new_bob.id
+        );
+    }
+
+    #[tokio::test]
+    async fn databases_of_newer_versions_are_left_alone() {
+        let db = Database::in_memory().await.unwrap();
+        Migrator::up(&db.pool, Some(5)).await.unwrap();
+        db.pool
+            .execute_unprepared(
+                r#"INSERT INTO "seaql_migrations" ("version", "applied_at")
+                    VALUES ('m20991231_000099_from_the_future', 0)"#,
+            )
+            .await
+            .unwrap();
+
+        match db.apply_migrations().await {
+            Err(Error::SchemaTooNew(unknown)) => {
+                assert_eq!(unknown, vec!["m20991231_000099_from_the_future"])
+            }
+            other => panic!("Expected SchemaTooNew, got {other:?}"),
+        }
+
+        // None of the pending migrations has been applied
+        let applied = Migrator::get_migration_models(&db.pool).await.unwrap();
+        assert_eq!(applied.len(), 6);
+        assert!(db.get_payments_in_group(1).await.is_err());
+    }
 }
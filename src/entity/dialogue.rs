@@ -0,0 +1,20 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "dialogue")]
+pub struct Model {
+    /// Telegram chat the dialogue is held in
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub chat_id: i64,
+    /// Telegram user the dialogue is held with, in private chats it's the same as the chat
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: i64,
+    /// JSON of the state the dialogue is in
+    pub state: String,
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
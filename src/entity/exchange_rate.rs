@@ -0,0 +1,20 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "exchange_rate")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub date: Date,
+    /// ISO 4217 code, e.g. `EUR`
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub from_currency: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub to_currency: String,
+    /// How much of `to_currency` one unit of `from_currency` is worth
+    pub rate: Decimal,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,26 +1,66 @@
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "expense")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i64,
-    pub username: String,
+    /// The only payer or, if there are several of them in `expense_payer`, the one who paid the most
+    pub payer: i64,
+    pub created_by: i64,
     pub group_id: i64,
+    /// In `currency`, which isn't necessarily the currency of the group
     pub amount: Decimal,
+    /// ISO 4217 code, e.g. `EUR`
+    pub currency: String,
+    /// How much of the group currency one unit of `currency` was worth when the expense was made
+    pub exchange_rate: Decimal,
     pub note: String,
+    pub split_mode: SplitMode,
+    pub created_at: DateTimeUtc,
+    pub updated_at: DateTimeUtc,
+}
+
+/// Defines how `expense_participant::Model::share` is interpreted
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum SplitMode {
+    /// Everybody pays the same part, shares are ignored
+    #[default]
+    #[sea_orm(string_value = "equal")]
+    Equal,
+    /// Share is the exact amount paid by the participant
+    #[sea_orm(string_value = "exact")]
+    Exact,
+    /// Share is the percent of the amount paid by the participant
+    #[sea_orm(string_value = "percent")]
+    Percent,
+    /// Share is the weight of the participant, e.g. 2:1:1
+    #[sea_orm(string_value = "shares")]
+    Shares,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(
         belongs_to = "super::user::Entity",
-        from = "Column::Username",
-        to = "super::user::Column::Username",
+        from = "Column::Payer",
+        to = "super::user::Column::Id",
         on_update = "Cascade",
         on_delete = "Cascade"
     )]
     User,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::CreatedBy",
+        to = "super::user::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Creator,
     #[sea_orm(
         belongs_to = "super::group::Entity",
         from = "Column::GroupId",
@@ -29,6 +69,10 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     Group,
+    #[sea_orm(has_many = "super::expense_participant::Entity")]
+    ExpenseParticipant,
+    #[sea_orm(has_many = "super::expense_payer::Entity")]
+    ExpensePayer,
 }
 
 impl Related<super::user::Entity> for Entity {
@@ -43,4 +87,16 @@ impl Related<super::group::Entity> for Entity {
     }
 }
 
+impl Related<super::expense_participant::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ExpenseParticipant.def()
+    }
+}
+
+impl Related<super::expense_payer::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ExpensePayer.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
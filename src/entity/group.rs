@@ -6,6 +6,31 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i64,
     pub name: String,
+    /// ISO 4217 code of the currency balances are computed in
+    pub currency: String,
+    pub simplification: Simplification,
+    /// Telegram group chat where commands act on this group without asking for it
+    pub chat_id: Option<i64>,
+    /// Archived groups are read-only and kept out of the way until they are unarchived
+    pub archived_at: Option<DateTimeUtc>,
+    pub created_at: DateTimeUtc,
+    pub updated_at: DateTimeUtc,
+}
+
+/// Defines how debts of a group are turned into transfers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum Simplification {
+    /// The largest debtors pay the largest creditors first
+    #[default]
+    #[sea_orm(string_value = "greedy")]
+    Greedy,
+    /// As few transfers as possible, which takes an exhaustive search in small groups
+    #[sea_orm(string_value = "minimal")]
+    Minimal,
+    /// Everybody pays back only those who paid for them, netted per pair of members
+    #[sea_orm(string_value = "pairwise")]
+    Pairwise,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -24,4 +49,10 @@ impl Related<super::user_group::Entity> for Entity {
     }
 }
 
+impl Model {
+    pub fn is_archived(&self) -> bool {
+        self.archived_at.is_some()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,48 @@
+use sea_orm::entity::prelude::*;
+
+/// Opens a group to whoever follows its deep link, see `/invite`
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "invite")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    /// Payload of the `/start` command the deep link sends
+    #[sea_orm(unique)]
+    pub token: String,
+    pub group_id: i64,
+    pub created_by: i64,
+    /// The link stops working after that, if set
+    pub expires_at: Option<DateTimeUtc>,
+    /// How many people can join by the link, unlimited if not set
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::group::Entity",
+        from = "Column::GroupId",
+        to = "super::group::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Group,
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::CreatedBy",
+        to = "super::user::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    CreatedBy,
+}
+
+impl Related<super::group::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Group.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,4 +1,11 @@
+pub mod departure;
+pub mod dialogue;
+pub mod exchange_rate;
 pub mod expense;
+pub mod expense_participant;
+pub mod expense_payer;
 pub mod group;
+pub mod invite;
+pub mod payment;
 pub mod user;
 pub mod user_group;
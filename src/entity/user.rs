@@ -3,8 +3,30 @@ use sea_orm::entity::prelude::*;
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "user")]
 pub struct Model {
-    #[sea_orm(primary_key, auto_increment = false)]
-    pub username: String, // corresponds to user's telegram id
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    /// Telegram user id. Missing for members who were added by @username, but haven't talked to the bot yet,
+    /// and for placeholders of people who aren't on Telegram at all
+    #[sea_orm(unique)]
+    pub telegram_id: Option<i64>,
+    /// Telegram @username. It may change at any moment, so it's not used as identity
+    pub username: Option<String>,
+    pub display_name: String,
+}
+
+impl Model {
+    /// How the user is shown in messages
+    pub fn mention(&self) -> String {
+        match self.username {
+            Some(ref username) => username.clone(),
+            None => self.display_name.clone(),
+        }
+    }
+
+    /// Named member without a Telegram account. Can only be merged into a real user by hand
+    pub fn is_placeholder(&self) -> bool {
+        self.telegram_id.is_none() && self.username.is_none()
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
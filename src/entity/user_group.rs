@@ -1,12 +1,40 @@
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "user_group")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
-    pub username: String,
+    pub user_id: i64,
     #[sea_orm(primary_key, auto_increment = false)]
     pub group_id: i64,
+    pub role: Role,
+    pub created_at: DateTimeUtc,
+    pub updated_at: DateTimeUtc,
+}
+
+/// What a member is allowed to do in a group
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(None)")]
+pub enum Role {
+    /// Whoever created the group. Has every right of an admin and can't be demoted
+    #[sea_orm(string_value = "owner")]
+    Owner,
+    /// Changes settings, manages members and edits expenses of others
+    #[sea_orm(string_value = "admin")]
+    Admin,
+    /// Adds expenses and edits their own ones
+    #[default]
+    #[sea_orm(string_value = "member")]
+    Member,
+}
+
+impl Role {
+    pub fn is_admin(self) -> bool {
+        matches!(self, Self::Owner | Self::Admin)
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -21,8 +49,8 @@ pub enum Relation {
     Group,
     #[sea_orm(
         belongs_to = "super::user::Entity",
-        from = "Column::Username",
-        to = "super::user::Column::Username",
+        from = "Column::UserId",
+        to = "super::user::Column::Id",
         on_update = "Cascade",
         on_delete = "Cascade"
     )]
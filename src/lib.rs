@@ -1,6 +1,9 @@
 pub mod bot;
-mod cli;
+pub mod cli;
 mod controller;
 mod db;
 mod entity;
 mod migration;
+pub mod rates;
+mod settlement;
+mod storage;
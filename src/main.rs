@@ -1,4 +1,8 @@
-use rust_splittea_bot::bot;
+use rust_splittea_bot::{
+    bot,
+    cli::{Command, CLI},
+    rates,
+};
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
 
@@ -9,7 +13,13 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     info!("Intiailizing splittea...");
-    bot::run().await?;
+    match CLI.command {
+        Some(Command::ImportRates { ref file }) => {
+            let imported = rates::import(&CLI.database, file).await?;
+            info!("Imported {imported} exchange rates from {file:?}");
+        }
+        None => bot::run().await?,
+    }
 
     Ok(())
 }
@@ -0,0 +1,75 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ExpenseParticipant::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ExpenseParticipant::ExpenseId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ExpenseParticipant::Username)
+                            .string()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .name("pk-expense_participant")
+                            .col(ExpenseParticipant::ExpenseId)
+                            .col(ExpenseParticipant::Username),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-expense_participant-expense_id")
+                            .from(ExpenseParticipant::Table, ExpenseParticipant::ExpenseId)
+                            .to(Expense::Table, Expense::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-expense_participant-username")
+                            .from(ExpenseParticipant::Table, ExpenseParticipant::Username)
+                            .to(User::Table, User::Username)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ExpenseParticipant::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Username,
+}
+
+#[derive(DeriveIden)]
+enum Expense {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum ExpenseParticipant {
+    Table,
+    ExpenseId,
+    Username,
+}
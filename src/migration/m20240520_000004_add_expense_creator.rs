@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Expense::Table)
+                    .rename_column(Expense::Username, Expense::Payer)
+                    .to_owned(),
+            )
+            .await?;
+
+        // SQLite can't add a foreign key with `ALTER TABLE`, unless it's declared inline with the column
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"ALTER TABLE "expense" ADD COLUMN "created_by" text
+                REFERENCES "user" ("username") ON DELETE CASCADE ON UPDATE CASCADE"#,
+        )
+        .await?;
+
+        // Before this migration the expense was always logged by the person who paid it
+        let update = Query::update()
+            .table(Expense::Table)
+            .value(Expense::CreatedBy, Expr::col(Expense::Payer))
+            .to_owned();
+        manager.exec_stmt(update).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Expense::Table)
+                    .drop_column(Expense::CreatedBy)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Expense::Table)
+                    .rename_column(Expense::Payer, Expense::Username)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Expense {
+    Table,
+    Username,
+    Payer,
+    CreatedBy,
+}
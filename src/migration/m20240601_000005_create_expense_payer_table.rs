@@ -0,0 +1,69 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ExpensePayer::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ExpensePayer::ExpenseId).integer().not_null())
+                    .col(ColumnDef::new(ExpensePayer::Username).string().not_null())
+                    .col(ColumnDef::new(ExpensePayer::Amount).decimal().not_null())
+                    .primary_key(
+                        Index::create()
+                            .name("pk-expense_payer")
+                            .col(ExpensePayer::ExpenseId)
+                            .col(ExpensePayer::Username),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-expense_payer-expense_id")
+                            .from(ExpensePayer::Table, ExpensePayer::ExpenseId)
+                            .to(Expense::Table, Expense::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-expense_payer-username")
+                            .from(ExpensePayer::Table, ExpensePayer::Username)
+                            .to(User::Table, User::Username)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ExpensePayer::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Username,
+}
+
+#[derive(DeriveIden)]
+enum Expense {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum ExpensePayer {
+    Table,
+    ExpenseId,
+    Username,
+    Amount,
+}
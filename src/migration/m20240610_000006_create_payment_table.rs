@@ -0,0 +1,87 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Payment::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Payment::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Payment::GroupId).integer().not_null())
+                    .col(ColumnDef::new(Payment::FromUser).string().not_null())
+                    .col(ColumnDef::new(Payment::ToUser).string().not_null())
+                    .col(ColumnDef::new(Payment::Amount).decimal().not_null())
+                    .col(
+                        ColumnDef::new(Payment::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-payment-group_id")
+                            .from(Payment::Table, Payment::GroupId)
+                            .to(Group::Table, Group::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-payment-from_user")
+                            .from(Payment::Table, Payment::FromUser)
+                            .to(User::Table, User::Username)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-payment-to_user")
+                            .from(Payment::Table, Payment::ToUser)
+                            .to(User::Table, User::Username)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Payment::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Username,
+}
+
+#[derive(DeriveIden)]
+enum Group {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Payment {
+    Table,
+    Id,
+    GroupId,
+    FromUser,
+    ToUser,
+    Amount,
+    CreatedAt,
+}
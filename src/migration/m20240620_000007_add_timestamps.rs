@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Existing rows don't know when they were created, so they get the time of the migration
+        let now = chrono::Utc::now();
+
+        for table in [
+            Expense::Table.into_iden(),
+            Group::Table.into_iden(),
+            UserGroup::Table.into_iden(),
+        ] {
+            // SQLite can neither add several columns within one statement,
+            // nor add a column with non-constant default
+            for column in [Timestamps::CreatedAt, Timestamps::UpdatedAt] {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(table.clone())
+                            .add_column(ColumnDef::new(column).timestamp_with_time_zone())
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+
+            let update = Query::update()
+                .table(table)
+                .value(Timestamps::CreatedAt, now)
+                .value(Timestamps::UpdatedAt, now)
+                .to_owned();
+            manager.exec_stmt(update).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in [
+            Expense::Table.into_iden(),
+            Group::Table.into_iden(),
+            UserGroup::Table.into_iden(),
+        ] {
+            for column in [Timestamps::CreatedAt, Timestamps::UpdatedAt] {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(table.clone())
+                            .drop_column(column)
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Expense {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum Group {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum UserGroup {
+    Table,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum Timestamps {
+    CreatedAt,
+    UpdatedAt,
+}
@@ -0,0 +1,126 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{ConnectionTrait, TransactionTrait},
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// SQLite can't change a primary key or a foreign key of an existing table, so every table
+// referencing `user` is rebuilt: new tables are created next to the old ones, the data is copied,
+// the old tables are dropped (children first, so no cascades fire) and the new ones take their names.
+// Renaming a table also rewrites foreign keys pointing to it, so `user_new` references become `user`.
+const UP: &[&str] = &[
+    r#"CREATE TABLE "user_new" (
+        "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+        "telegram_id" integer UNIQUE,
+        "username" text,
+        "display_name" text NOT NULL
+    )"#,
+    r#"INSERT INTO "user_new" ("username", "display_name")
+        SELECT "username", "username" FROM "user""#,
+    r#"CREATE TABLE "user_group_new" (
+        "user_id" integer NOT NULL,
+        "group_id" integer NOT NULL,
+        "created_at" text,
+        "updated_at" text,
+        CONSTRAINT "pk-user_group" PRIMARY KEY ("user_id", "group_id"),
+        FOREIGN KEY ("user_id") REFERENCES "user_new" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+        FOREIGN KEY ("group_id") REFERENCES "group" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+    )"#,
+    r#"INSERT INTO "user_group_new" ("user_id", "group_id", "created_at", "updated_at")
+        SELECT "u"."id", "ug"."group_id", "ug"."created_at", "ug"."updated_at"
+        FROM "user_group" AS "ug" JOIN "user_new" AS "u" ON "u"."username" = "ug"."username""#,
+    r#"CREATE TABLE "expense_new" (
+        "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+        "note" text,
+        "amount" real NOT NULL,
+        "payer" integer NOT NULL,
+        "created_by" integer NOT NULL,
+        "group_id" integer NOT NULL,
+        "split_mode" text NOT NULL DEFAULT 'equal',
+        "created_at" text,
+        "updated_at" text,
+        FOREIGN KEY ("payer") REFERENCES "user_new" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+        FOREIGN KEY ("created_by") REFERENCES "user_new" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+        FOREIGN KEY ("group_id") REFERENCES "group" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+    )"#,
+    r#"INSERT INTO "expense_new"
+        ("id", "note", "amount", "payer", "created_by", "group_id", "split_mode", "created_at", "updated_at")
+        SELECT "e"."id", "e"."note", "e"."amount", "p"."id", "c"."id", "e"."group_id", "e"."split_mode",
+            "e"."created_at", "e"."updated_at"
+        FROM "expense" AS "e"
+        JOIN "user_new" AS "p" ON "p"."username" = "e"."payer"
+        JOIN "user_new" AS "c" ON "c"."username" = "e"."created_by""#,
+    r#"CREATE TABLE "expense_participant_new" (
+        "expense_id" integer NOT NULL,
+        "user_id" integer NOT NULL,
+        "share" real,
+        CONSTRAINT "pk-expense_participant" PRIMARY KEY ("expense_id", "user_id"),
+        FOREIGN KEY ("expense_id") REFERENCES "expense_new" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+        FOREIGN KEY ("user_id") REFERENCES "user_new" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+    )"#,
+    r#"INSERT INTO "expense_participant_new" ("expense_id", "user_id", "share")
+        SELECT "ep"."expense_id", "u"."id", "ep"."share"
+        FROM "expense_participant" AS "ep" JOIN "user_new" AS "u" ON "u"."username" = "ep"."username""#,
+    r#"CREATE TABLE "expense_payer_new" (
+        "expense_id" integer NOT NULL,
+        "user_id" integer NOT NULL,
+        "amount" real NOT NULL,
+        CONSTRAINT "pk-expense_payer" PRIMARY KEY ("expense_id", "user_id"),
+        FOREIGN KEY ("expense_id") REFERENCES "expense_new" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+        FOREIGN KEY ("user_id") REFERENCES "user_new" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+    )"#,
+    r#"INSERT INTO "expense_payer_new" ("expense_id", "user_id", "amount")
+        SELECT "ep"."expense_id", "u"."id", "ep"."amount"
+        FROM "expense_payer" AS "ep" JOIN "user_new" AS "u" ON "u"."username" = "ep"."username""#,
+    r#"CREATE TABLE "payment_new" (
+        "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT,
+        "group_id" integer NOT NULL,
+        "from_user" integer NOT NULL,
+        "to_user" integer NOT NULL,
+        "amount" real NOT NULL,
+        "created_at" text NOT NULL,
+        FOREIGN KEY ("group_id") REFERENCES "group" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+        FOREIGN KEY ("from_user") REFERENCES "user_new" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+        FOREIGN KEY ("to_user") REFERENCES "user_new" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+    )"#,
+    r#"INSERT INTO "payment_new" ("id", "group_id", "from_user", "to_user", "amount", "created_at")
+        SELECT "p"."id", "p"."group_id", "f"."id", "t"."id", "p"."amount", "p"."created_at"
+        FROM "payment" AS "p"
+        JOIN "user_new" AS "f" ON "f"."username" = "p"."from_user"
+        JOIN "user_new" AS "t" ON "t"."username" = "p"."to_user""#,
+    r#"DROP TABLE "expense_participant""#,
+    r#"DROP TABLE "expense_payer""#,
+    r#"DROP TABLE "payment""#,
+    r#"DROP TABLE "expense""#,
+    r#"DROP TABLE "user_group""#,
+    r#"DROP TABLE "user""#,
+    r#"ALTER TABLE "user_new" RENAME TO "user""#,
+    r#"ALTER TABLE "user_group_new" RENAME TO "user_group""#,
+    r#"ALTER TABLE "expense_new" RENAME TO "expense""#,
+    r#"ALTER TABLE "expense_participant_new" RENAME TO "expense_participant""#,
+    r#"ALTER TABLE "expense_payer_new" RENAME TO "expense_payer""#,
+    r#"ALTER TABLE "payment_new" RENAME TO "payment""#,
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // A single connection has to run the whole rebuild, otherwise another connection of the pool
+        // may still see the old schema when the tables get renamed
+        let txn = manager.get_connection().begin().await?;
+        for statement in UP {
+            txn.execute_unprepared(statement).await?;
+        }
+
+        txn.commit().await
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // Users who never had a @username can't be keyed by it again
+        Err(DbErr::Migration(
+            "Keying users by their telegram id can't be reverted".to_owned(),
+        ))
+    }
+}
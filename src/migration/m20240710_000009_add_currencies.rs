@@ -0,0 +1,129 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Groups created before currencies were introduced get this one. It can be changed with `/setcurrency`
+const DEFAULT_CURRENCY: &str = "EUR";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite can't add several columns within one statement
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Group::Table)
+                    .add_column(
+                        ColumnDef::new(Currency::Currency)
+                            .string()
+                            .not_null()
+                            .default(DEFAULT_CURRENCY),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Expense::Table)
+                    .add_column(
+                        ColumnDef::new(Currency::Currency)
+                            .string()
+                            .not_null()
+                            .default(DEFAULT_CURRENCY),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Amount of the group currency one unit of the expense currency was worth
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Expense::Table)
+                    .add_column(
+                        ColumnDef::new(Expense::ExchangeRate)
+                            .decimal()
+                            .not_null()
+                            .default(1),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Payment::Table)
+                    .add_column(
+                        ColumnDef::new(Currency::Currency)
+                            .string()
+                            .not_null()
+                            .default(DEFAULT_CURRENCY),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Payment::Table)
+                    .drop_column(Currency::Currency)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Expense::Table)
+                    .drop_column(Expense::ExchangeRate)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Expense::Table)
+                    .drop_column(Currency::Currency)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Group::Table)
+                    .drop_column(Currency::Currency)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Group {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum Expense {
+    Table,
+    ExchangeRate,
+}
+
+#[derive(DeriveIden)]
+enum Payment {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum Currency {
+    Currency,
+}
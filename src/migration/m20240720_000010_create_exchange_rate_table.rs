@@ -0,0 +1,48 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ExchangeRate::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ExchangeRate::Date).date().not_null())
+                    .col(
+                        ColumnDef::new(ExchangeRate::FromCurrency)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ExchangeRate::ToCurrency).string().not_null())
+                    .col(ColumnDef::new(ExchangeRate::Rate).decimal().not_null())
+                    .primary_key(
+                        Index::create()
+                            .name("pk-exchange_rate")
+                            .col(ExchangeRate::Date)
+                            .col(ExchangeRate::FromCurrency)
+                            .col(ExchangeRate::ToCurrency),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ExchangeRate::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ExchangeRate {
+    Table,
+    Date,
+    FromCurrency,
+    ToCurrency,
+    Rate,
+}
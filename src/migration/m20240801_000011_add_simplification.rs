@@ -0,0 +1,41 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Existing groups keep the greedy matching they've always had
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Group::Table)
+                    .add_column(
+                        ColumnDef::new(Group::Simplification)
+                            .string()
+                            .not_null()
+                            .default("greedy"),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Group::Table)
+                    .drop_column(Group::Simplification)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Group {
+    Table,
+    Simplification,
+}
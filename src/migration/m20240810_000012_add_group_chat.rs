@@ -0,0 +1,57 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Telegram group chat the group was created in. Groups created in private chats have none
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Group::Table)
+                    .add_column(ColumnDef::new(Group::ChatId).big_integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        // A chat can be bound to a single group only
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-group-chat_id")
+                    .table(Group::Table)
+                    .col(Group::ChatId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-group-chat_id")
+                    .table(Group::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Group::Table)
+                    .drop_column(Group::ChatId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Group {
+    Table,
+    ChatId,
+}
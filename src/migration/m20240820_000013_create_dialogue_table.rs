@@ -0,0 +1,40 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Dialogue::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Dialogue::ChatId)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Dialogue::State).text().not_null())
+                    .col(ColumnDef::new(Dialogue::UpdatedAt).timestamp().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Dialogue::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Dialogue {
+    Table,
+    ChatId,
+    State,
+    UpdatedAt,
+}
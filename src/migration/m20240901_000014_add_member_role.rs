@@ -0,0 +1,57 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserGroup::Table)
+                    .add_column(
+                        ColumnDef::new(UserGroup::Role)
+                            .string()
+                            .not_null()
+                            .default("member"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Creators of existing groups weren't recorded. The one who joined a group first is the
+        // closest guess, since creating a group always made its creator the first member
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"UPDATE "user_group" SET "role" = 'owner'
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM "user_group" AS "earlier"
+                    WHERE "earlier"."group_id" = "user_group"."group_id"
+                        AND ("earlier"."created_at" < "user_group"."created_at"
+                            OR ("earlier"."created_at" = "user_group"."created_at"
+                                AND "earlier"."user_id" < "user_group"."user_id"))
+                )"#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserGroup::Table)
+                    .drop_column(UserGroup::Role)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserGroup {
+    Table,
+    Role,
+}
@@ -0,0 +1,32 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Expenses without participants used to be split among whoever was a member at the time
+        // balances were computed. They are pinned to the current members, so that balances stay
+        // the same now and members who join later aren't charged for them
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"INSERT INTO "expense_participant" ("expense_id", "user_id", "share")
+                SELECT "expense"."id", "user_group"."user_id", NULL
+                FROM "expense"
+                JOIN "user_group" ON "user_group"."group_id" = "expense"."group_id"
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM "expense_participant"
+                    WHERE "expense_participant"."expense_id" = "expense"."id"
+                )"#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // There is no telling which participants were written down by the migration
+        Ok(())
+    }
+}
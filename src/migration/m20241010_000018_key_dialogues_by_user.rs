@@ -0,0 +1,57 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{ConnectionTrait, TransactionTrait},
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// Every member of a group chat holds a dialogue of their own, so the table is keyed by the chat along
+// with the user. Dialogues of private chats are kept, since the id of such a chat is the id of the user.
+// Those of group chats are dropped, there is no telling whose they were
+const UP: &[&str] = &[
+    r#"CREATE TABLE "dialogue_new" (
+        "chat_id" bigint NOT NULL,
+        "user_id" bigint NOT NULL,
+        "state" text NOT NULL,
+        "updated_at" text NOT NULL,
+        CONSTRAINT "pk-dialogue" PRIMARY KEY ("chat_id", "user_id")
+    )"#,
+    r#"INSERT INTO "dialogue_new" ("chat_id", "user_id", "state", "updated_at")
+        SELECT "chat_id", "chat_id", "state", "updated_at" FROM "dialogue" WHERE "chat_id" > 0"#,
+    r#"DROP TABLE "dialogue""#,
+    r#"ALTER TABLE "dialogue_new" RENAME TO "dialogue""#,
+];
+
+const DOWN: &[&str] = &[
+    r#"CREATE TABLE "dialogue_old" (
+        "chat_id" bigint NOT NULL PRIMARY KEY,
+        "state" text NOT NULL,
+        "updated_at" text NOT NULL
+    )"#,
+    r#"INSERT INTO "dialogue_old" ("chat_id", "state", "updated_at")
+        SELECT "chat_id", "state", "updated_at" FROM "dialogue" WHERE "chat_id" = "user_id""#,
+    r#"DROP TABLE "dialogue""#,
+    r#"ALTER TABLE "dialogue_old" RENAME TO "dialogue""#,
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let txn = manager.get_connection().begin().await?;
+        for statement in UP {
+            txn.execute_unprepared(statement).await?;
+        }
+
+        txn.commit().await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let txn = manager.get_connection().begin().await?;
+        for statement in DOWN {
+            txn.execute_unprepared(statement).await?;
+        }
+
+        txn.commit().await
+    }
+}
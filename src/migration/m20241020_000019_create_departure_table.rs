@@ -0,0 +1,73 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Departure::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Departure::UserId).integer().not_null())
+                    .col(ColumnDef::new(Departure::GroupId).integer().not_null())
+                    .col(
+                        ColumnDef::new(Departure::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .name("pk-departure")
+                            .col(Departure::UserId)
+                            .col(Departure::GroupId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-departure-user_id")
+                            .from(Departure::Table, Departure::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-departure-group_id")
+                            .from(Departure::Table, Departure::GroupId)
+                            .to(Group::Table, Group::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Departure::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Group {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Departure {
+    Table,
+    UserId,
+    GroupId,
+    CreatedAt,
+}
@@ -1,12 +1,50 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20240501_000002_create_expense_participant_table;
+mod m20240515_000003_add_split_mode;
+mod m20240520_000004_add_expense_creator;
+mod m20240601_000005_create_expense_payer_table;
+mod m20240610_000006_create_payment_table;
+mod m20240620_000007_add_timestamps;
+mod m20240701_000008_key_users_by_id;
+mod m20240710_000009_add_currencies;
+mod m20240720_000010_create_exchange_rate_table;
+mod m20240801_000011_add_simplification;
+mod m20240810_000012_add_group_chat;
+mod m20240820_000013_create_dialogue_table;
+mod m20240901_000014_add_member_role;
+mod m20240910_000015_add_group_archive;
+mod m20240920_000016_create_invite_table;
+mod m20241001_000017_record_all_participants;
+mod m20241010_000018_key_dialogues_by_user;
+mod m20241020_000019_create_departure_table;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20240501_000002_create_expense_participant_table::Migration),
+            Box::new(m20240515_000003_add_split_mode::Migration),
+            Box::new(m20240520_000004_add_expense_creator::Migration),
+            Box::new(m20240601_000005_create_expense_payer_table::Migration),
+            Box::new(m20240610_000006_create_payment_table::Migration),
+            Box::new(m20240620_000007_add_timestamps::Migration),
+            Box::new(m20240701_000008_key_users_by_id::Migration),
+            Box::new(m20240710_000009_add_currencies::Migration),
+            Box::new(m20240720_000010_create_exchange_rate_table::Migration),
+            Box::new(m20240801_000011_add_simplification::Migration),
+            Box::new(m20240810_000012_add_group_chat::Migration),
+            Box::new(m20240820_000013_create_dialogue_table::Migration),
+            Box::new(m20240901_000014_add_member_role::Migration),
+            Box::new(m20240910_000015_add_group_archive::Migration),
+            Box::new(m20240920_000016_create_invite_table::Migration),
+            Box::new(m20241001_000017_record_all_participants::Migration),
+            Box::new(m20241010_000018_key_dialogues_by_user::Migration),
+            Box::new(m20241020_000019_create_departure_table::Migration),
+        ]
     }
 }
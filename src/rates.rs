@@ -0,0 +1,197 @@
+use crate::{db::Database, entity::exchange_rate, settlement};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::path::Path;
+
+/// Currency the European Central Bank quotes all of its rates against
+const ECB_BASE_CURRENCY: &str = "EUR";
+
+/// Imports exchange rates from a file published by the ECB, either CSV (eurofxref.csv,
+/// eurofxref-hist.csv) or XML (eurofxref-daily.xml, eurofxref-hist.xml). Returns the number of rates
+pub async fn import(db_path: &Path, file: &Path) -> anyhow::Result<usize> {
+    let text = std::fs::read_to_string(file)
+        .map_err(|err| anyhow::anyhow!("Reading {file:?} failed. Err: {err}"))?;
+    let rates = if text.trim_start().starts_with('<') {
+        parse_xml(&text)?
+    } else {
+        parse_csv(&text)?
+    };
+
+    let db = Database::new(db_path)
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to connect to database {db_path:?}: {err}"))?;
+    db.apply_migrations()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to apply database migrations. Err: {err}"))?;
+    db.upsert_exchange_rates(&rates)
+        .await
+        .map_err(|err| anyhow::anyhow!("Exchange rates insertion failed. Err: {err}"))?;
+
+    Ok(rates.len())
+}
+
+/// ECB files use ISO dates, except for the daily CSV which has e.g. `15 March 2024`
+fn parse_date(text: &str) -> anyhow::Result<NaiveDate> {
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(text, "%d %B %Y"))
+        .map_err(|err| anyhow::anyhow!("Invalid date `{text}`. Err: {err}"))
+}
+
+fn ecb_rate(date: NaiveDate, currency: &str, rate: &str) -> anyhow::Result<exchange_rate::Model> {
+    let value = rate
+        .parse::<Decimal>()
+        .map_err(|err| anyhow::anyhow!("Invalid rate `{rate}` of {currency}. Err: {err}"))?;
+    settlement::validate_rate(value)
+        .map_err(|err| anyhow::anyhow!("Invalid rate `{rate}` of {currency}. Err: {err}"))?;
+
+    Ok(exchange_rate::Model {
+        date,
+        from_currency: ECB_BASE_CURRENCY.to_owned(),
+        to_currency: currency.to_ascii_uppercase(),
+        rate: value,
+    })
+}
+
+/// A `Date` column followed by a column per currency and a row per day. Missing rates are `N/A`
+fn parse_csv(text: &str) -> anyhow::Result<Vec<exchange_rate::Model>> {
+    let mut lines = text.lines().filter(|x| !x.trim().is_empty());
+    let header = lines
+        .next()
+        .ok_or(anyhow::anyhow!("The rates file is empty"))?;
+    let currencies: Vec<&str> = header.split(',').map(str::trim).collect();
+    if !currencies
+        .first()
+        .is_some_and(|x| x.eq_ignore_ascii_case("date"))
+    {
+        anyhow::bail!("The first column of the rates file must be `Date`");
+    }
+
+    let mut rates = Vec::new();
+    for line in lines {
+        let mut fields = line.split(',').map(str::trim);
+        let date = parse_date(fields.next().unwrap_or_default())?;
+
+        for (currency, rate) in currencies.iter().skip(1).zip(fields) {
+            // Daily files end every line with a comma
+            if currency.is_empty() || rate.is_empty() || rate == "N/A" {
+                continue;
+            }
+            rates.push(ecb_rate(date, currency, rate)?);
+        }
+    }
+
+    Ok(rates)
+}
+
+/// Value of an XML attribute within a single tag
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag
+        .match_indices(name)
+        .map(|(index, _)| index)
+        .find(|&index| {
+            tag[..index].ends_with(char::is_whitespace)
+                && tag[index + name.len()..].starts_with('=')
+        })?
+        + name.len()
+        + 1;
+
+    let quote = tag[start..]
+        .chars()
+        .next()
+        .filter(|c| *c == '"' || *c == '\'')?;
+    let value = &tag[start + 1..];
+    value.find(quote).map(|end| &value[..end])
+}
+
+/// `<Cube time="...">` elements, each holding `<Cube currency="..." rate="..."/>` of that day
+fn parse_xml(text: &str) -> anyhow::Result<Vec<exchange_rate::Model>> {
+    let mut rates = Vec::new();
+    let mut date = None;
+
+    for tag in text.split('<').skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        if !tag.starts_with("Cube") {
+            continue;
+        }
+
+        if let Some(time) = attribute(tag, "time") {
+            date = Some(parse_date(time)?);
+        }
+
+        if let (Some(currency), Some(rate)) = (attribute(tag, "currency"), attribute(tag, "rate")) {
+            let date = date.ok_or(anyhow::anyhow!("Rate of {currency} has no date"))?;
+            rates.push(ecb_rate(date, currency, rate)?);
+        }
+    }
+
+    if rates.is_empty() {
+        anyhow::bail!("No exchange rates found in the file");
+    }
+
+    Ok(rates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(date: &str, currency: &str, rate: Decimal) -> exchange_rate::Model {
+        exchange_rate::Model {
+            date: parse_date(date).unwrap(),
+            from_currency: ECB_BASE_CURRENCY.to_owned(),
+            to_currency: currency.to_owned(),
+            rate,
+        }
+    }
+
+    #[test]
+    fn daily_csv_is_parsed() {
+        let rates = parse_csv(include_str!("../tests/fixtures/eurofxref.csv")).unwrap();
+        assert_eq!(
+            rates,
+            vec![
+                rate("2024-03-15", "USD", Decimal::new(10892, 4)),
+                rate("2024-03-15", "JPY", Decimal::new(16167, 2)),
+                rate("2024-03-15", "BGN", Decimal::new(19558, 4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn historical_csv_skips_missing_rates() {
+        let rates = parse_csv(include_str!("../tests/fixtures/eurofxref-hist.csv")).unwrap();
+        assert_eq!(
+            rates,
+            vec![
+                rate("2024-03-15", "USD", Decimal::new(10892, 4)),
+                rate("2024-03-15", "JPY", Decimal::new(16167, 2)),
+                rate("2024-03-14", "USD", Decimal::new(10925, 4)),
+                rate("2024-03-14", "JPY", Decimal::new(1619, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn xml_is_parsed_with_either_quotes() {
+        let rates = parse_xml(include_str!("../tests/fixtures/eurofxref-daily.xml")).unwrap();
+        assert_eq!(
+            rates,
+            vec![
+                rate("2024-03-15", "USD", Decimal::new(10892, 4)),
+                rate("2024-03-15", "JPY", Decimal::new(16167, 2)),
+                rate("2024-03-14", "USD", Decimal::new(10925, 4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_files_are_refused() {
+        assert!(parse_csv("").is_err());
+        assert!(parse_csv("Currency,USD\n2024-03-15,1.0892\n").is_err());
+        assert!(parse_csv("Date,USD\nyesterday,1.0892\n").is_err());
+        assert!(parse_csv("Date,USD\n2024-03-15,lots\n").is_err());
+        assert!(parse_csv("Date,USD\n2024-03-15,0\n").is_err());
+        assert!(parse_xml("<Cube><Cube currency='USD' rate='1.0892'/></Cube>").is_err());
+        assert!(parse_xml("<Envelope></Envelope>").is_err());
+    }
+}
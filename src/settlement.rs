@@ -0,0 +1,1111 @@
+use crate::entity::{
+    expense::{self, SplitMode},
+    expense_participant, expense_payer,
+    group::Simplification,
+    payment, user,
+};
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap},
+};
+
+/// Single payment that has to be made in order to settle the group debt
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    pub from: i64,
+    pub to: i64,
+    pub amount: Decimal,
+}
+
+/// Short digest of suggested transfers, which tells whether they are still the ones that were shown.
+/// It's the FNV-1a hash of their text, so a confirmation that is pending while the bot is upgraded
+/// still matches
+pub fn fingerprint(transfers: &[Transfer]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    transfers
+        .iter()
+        .flat_map(|x| format!("{}>{}:{};", x.from, x.to, x.amount.normalize()).into_bytes())
+        .fold(OFFSET_BASIS, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+        })
+}
+
+/// Everything that affects the debt state of a group
+#[derive(Clone, Debug, Default)]
+pub struct Ledger {
+    /// Currency of the group, which balances are computed in
+    pub currency: String,
+    /// How balances of the group are turned into transfers
+    pub simplification: Simplification,
+    pub members: Vec<user::Model>,
+    /// Those who have left the group, but are still mentioned by its expenses or payments
+    pub former_members: Vec<user::Model>,
+    pub expenses: Vec<expense::Model>,
+    pub participants: Vec<expense_participant::Model>,
+    pub payers: Vec<expense_payer::Model>,
+    pub payments: Vec<payment::Model>,
+}
+
+/// Largest amount of an expense, a payment or a share. Together with `MAX_RATE` it keeps whatever
+/// a group adds up far from the limits of `Decimal`
+pub const MAX_AMOUNT: Decimal = Decimal::from_parts(1_000_000_000, 0, 0, false, 0);
+
+/// Largest exchange rate an expense can lock in
+pub const MAX_RATE: Decimal = Decimal::from_parts(1_000_000, 0, 0, false, 0);
+
+/// Smallest exchange rate, so that the inverse of a rate stays within `MAX_RATE`
+pub const MIN_RATE: Decimal = Decimal::from_parts(1, 0, 0, false, 6);
+
+/// Reasons why participant shares don't add up to an expense
+#[derive(Debug, PartialEq, Eq)]
+pub enum SplitError {
+    NoParticipants,
+    MissingShare,
+    NonPositiveShare,
+    NonPositiveAmount,
+    AmountTooLarge,
+    /// The amount is more precise than the minor unit of its currency
+    TooManyDecimals {
+        currency: String,
+        decimals: u32,
+    },
+    RateOutOfRange,
+    ExactSum {
+        expected: Decimal,
+        actual: Decimal,
+    },
+    PercentSum(Decimal),
+    PayersSum {
+        expected: Decimal,
+        actual: Decimal,
+    },
+}
+
+impl std::fmt::Display for SplitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::NoParticipants => write!(f, "The expense has no participants"),
+            Self::MissingShare => write!(f, "Every participant needs a share"),
+            Self::NonPositiveShare => write!(f, "Every share must be positive"),
+            Self::NonPositiveAmount => write!(f, "Amounts must be positive"),
+            Self::AmountTooLarge => write!(f, "Amounts can't be larger than {}", MAX_AMOUNT),
+            Self::TooManyDecimals {
+                ref currency,
+                decimals,
+            } => write!(f, "{} has only {} decimal places", currency, decimals),
+            Self::RateOutOfRange => write!(
+                f,
+                "Exchange rates must be between {} and {}",
+                MIN_RATE, MAX_RATE
+            ),
+            Self::ExactSum { expected, actual } => write!(
+                f,
+                "Exact amounts sum up to {}, but the expense is {}",
+                actual, expected
+            ),
+            Self::PercentSum(actual) => {
+                write!(f, "Percentages sum up to {}, but must be 100", actual)
+            }
+            Self::PayersSum { expected, actual } => write!(
+                f,
+                "Payers paid {} together, but the expense is {}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SplitError {}
+
+/// Returned when sums of a group don't fit into `Decimal`, which only happens to amounts
+/// recorded before `MAX_AMOUNT` and `MAX_RATE` were enforced
+#[derive(Debug, PartialEq, Eq)]
+pub struct Overflow;
+
+impl std::fmt::Display for Overflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Amounts in the group are too large to add up")
+    }
+}
+
+impl std::error::Error for Overflow {}
+
+/// Checks that an amount of an expense or a payment is positive and no larger than `MAX_AMOUNT`
+pub fn validate_amount(amount: Decimal) -> Result<(), SplitError> {
+    if amount <= Decimal::ZERO {
+        return Err(SplitError::NonPositiveAmount);
+    }
+
+    match amount > MAX_AMOUNT {
+        true => Err(SplitError::AmountTooLarge),
+        false => Ok(()),
+    }
+}
+
+/// Checks that an amount can be paid in the currency, i.e. is no more precise than its minor unit
+pub fn validate_minor_units(amount: Decimal, currency: &str) -> Result<(), SplitError> {
+    let decimals = minor_units(currency);
+    if amount.normalize().scale() > decimals {
+        return Err(SplitError::TooManyDecimals {
+            currency: currency.to_owned(),
+            decimals,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that an exchange rate is between `MIN_RATE` and `MAX_RATE`
+pub fn validate_rate(rate: Decimal) -> Result<(), SplitError> {
+    if rate < MIN_RATE || rate > MAX_RATE {
+        return Err(SplitError::RateOutOfRange);
+    }
+
+    Ok(())
+}
+
+/// Checks that participant shares are consistent with the split mode of an expense
+pub fn validate_split(
+    mode: SplitMode,
+    amount: Decimal,
+    shares: &[(i64, Option<Decimal>)],
+) -> Result<(), SplitError> {
+    if shares.is_empty() {
+        return Err(SplitError::NoParticipants);
+    }
+
+    if mode == SplitMode::Equal {
+        return Ok(());
+    }
+
+    let mut total = Decimal::ZERO;
+    for (_, share) in shares {
+        let share = share.ok_or(SplitError::MissingShare)?;
+        if share <= Decimal::ZERO {
+            return Err(SplitError::NonPositiveShare);
+        }
+        if share > MAX_AMOUNT {
+            return Err(SplitError::AmountTooLarge);
+        }
+        total += share;
+    }
+
+    match mode {
+        SplitMode::Exact if total != amount => Err(SplitError::ExactSum {
+            expected: amount,
+            actual: total,
+        }),
+        SplitMode::Percent if total != Decimal::ONE_HUNDRED => Err(SplitError::PercentSum(total)),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that contributions of several payers add up to the expense amount
+pub fn validate_payers(amount: Decimal, payers: &[(i64, Decimal)]) -> Result<(), SplitError> {
+    if payers.is_empty() {
+        return Ok(());
+    }
+
+    if payers.iter().any(|x| x.1 <= Decimal::ZERO) {
+        return Err(SplitError::NonPositiveShare);
+    }
+
+    if payers.iter().any(|x| x.1 > MAX_AMOUNT) {
+        return Err(SplitError::AmountTooLarge);
+    }
+
+    let total: Decimal = payers.iter().map(|x| x.1).sum();
+    if total != amount {
+        return Err(SplitError::PayersSum {
+            expected: amount,
+            actual: total,
+        });
+    }
+
+    Ok(())
+}
+
+/// Codes of the currencies in ISO 4217, sorted. Precious metals and other codes without a minor
+/// unit are left out
+const CURRENCIES: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT",
+    "BGN", "BHD", "BIF", "BMD", "BND", "BOB", "BOV", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD",
+    "CAD", "CDF", "CHE", "CHF", "CHW", "CLF", "CLP", "CNY", "COP", "COU", "CRC", "CUC", "CUP",
+    "CVE", "CZK", "DJF", "DKK", "DOP", "DZD", "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP",
+    "GEL", "GHS", "GIP", "GMD", "GNF", "GTQ", "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS",
+    "INR", "IQD", "IRR", "ISK", "JMD", "JOD", "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW",
+    "KWD", "KYD", "KZT", "LAK", "LBP", "LKR", "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD",
+    "MMK", "MNT", "MOP", "MRU", "MUR", "MVR", "MWK", "MXN", "MXV", "MYR", "MZN", "NAD", "NGN",
+    "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN", "PGK", "PHP", "PKR", "PLN", "PYG", "QAR",
+    "RON", "RSD", "RUB", "RWF", "SAR", "SBD", "SCR", "SDG", "SEK", "SGD", "SHP", "SLE", "SLL",
+    "SOS", "SRD", "SSP", "STN", "SVC", "SYP", "SZL", "THB", "TJS", "TMT", "TND", "TOP", "TRY",
+    "TTD", "TWD", "TZS", "UAH", "UGX", "USD", "USN", "UYI", "UYU", "UYW", "UZS", "VED", "VES",
+    "VND", "VUV", "WST", "XAF", "XCD", "XCG", "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWG", "ZWL",
+];
+
+/// Whether the code is one of the currencies in ISO 4217, e.g. `EUR` is, but `BBQ` isn't
+pub fn is_currency(code: &str) -> bool {
+    CURRENCIES.binary_search(&code).is_ok()
+}
+
+/// Number of decimal places of the minor unit of a currency, e.g. 2 for cents. Follows ISO 4217
+pub fn minor_units(currency: &str) -> u32 {
+    match currency {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX"
+        | "UYI" | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        "CLF" | "UYW" => 4,
+        _ => 2,
+    }
+}
+
+/// Sum that fails instead of panicking when it doesn't fit into `Decimal`
+fn checked_sum(values: impl IntoIterator<Item = Decimal>) -> Result<Decimal, Overflow> {
+    values
+        .into_iter()
+        .try_fold(Decimal::ZERO, |sum, x| sum.checked_add(x).ok_or(Overflow))
+}
+
+/// Splits `amount` proportionally to `weights`, rounding every part down to `decimals` places.
+/// Leftover minor units go one at a time to the parts that lost the most to rounding. Ties are broken
+/// round-robin starting at the `offset`-th part, so the same member doesn't get every extra cent.
+/// Parts always add up to `amount` rounded to `decimals` places. Without positive weights the split is equal
+fn allocate(
+    amount: Decimal,
+    weights: &[(i64, Decimal)],
+    decimals: u32,
+    offset: usize,
+) -> Result<Vec<(i64, Decimal)>, Overflow> {
+    if weights.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let amount = amount.round_dp(decimals);
+    let total = checked_sum(weights.iter().map(|x| x.1))?;
+    let exact: Vec<Decimal> = weights
+        .iter()
+        .map(|(_, weight)| match total > Decimal::ZERO {
+            true => amount
+                .checked_mul(*weight)
+                .and_then(|x| x.checked_div(total))
+                .ok_or(Overflow),
+            false => Ok(amount / Decimal::from(weights.len())),
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut parts: Vec<Decimal> = exact
+        .iter()
+        .map(|x| x.round_dp_with_strategy(decimals, RoundingStrategy::ToNegativeInfinity))
+        .collect();
+
+    let count = weights.len();
+    let mut order: Vec<usize> = (0..count).collect();
+    order.sort_by_key(|&i| {
+        (
+            std::cmp::Reverse(exact[i] - parts[i]),
+            (i + count - offset % count) % count,
+        )
+    });
+
+    let unit = Decimal::new(1, decimals);
+    let mut leftover = amount - checked_sum(parts.iter().copied())?;
+    for &i in order.iter().cycle() {
+        if leftover < unit {
+            break;
+        }
+        parts[i] += unit;
+        leftover -= unit;
+    }
+
+    Ok(weights
+        .iter()
+        .zip(parts)
+        .map(|((user_id, _), part)| (*user_id, part))
+        .collect())
+}
+
+/// How much every participant owes for a single expense, in whole minor units
+fn split(
+    amount: Decimal,
+    mode: SplitMode,
+    sharing: &[(i64, Option<Decimal>)],
+    decimals: u32,
+    offset: usize,
+) -> Result<Vec<(i64, Decimal)>, Overflow> {
+    // Exact amounts and percentages are proportional to the amount just like shares are,
+    // which also keeps them right once the expense is converted into another currency
+    let weights: Vec<(i64, Decimal)> = sharing
+        .iter()
+        .map(|(user_id, share)| match mode {
+            SplitMode::Equal => (*user_id, Decimal::ONE),
+            _ => (*user_id, share.unwrap_or_default()),
+        })
+        .collect();
+
+    allocate(amount, &weights, decimals, offset)
+}
+
+#[derive(Debug)]
+struct UserDebt {
+    user_id: i64,
+    debt: Decimal,
+}
+
+/// How much every member is owed by the group. Negative value means that this member owes to the group.
+/// Every expense is converted into the currency of the group with the exchange rate it has locked in.
+/// Every share is rounded to the minor unit of the currency, so balances always add up to zero
+pub fn balances(ledger: &Ledger) -> Result<BTreeMap<i64, Decimal>, Overflow> {
+    tally(
+        ledger,
+        minor_units(&ledger.currency),
+        |exp| Some(exp.exchange_rate),
+        |_| true,
+    )
+}
+
+/// Transfers that settle the group in its currency, simplified the way the group has chosen
+pub fn debts(ledger: &Ledger) -> Result<Vec<Transfer>, Overflow> {
+    match ledger.simplification {
+        Simplification::Greedy => Ok(transfers(&balances(ledger)?)),
+        Simplification::Minimal => minimal_transfers(&balances(ledger)?),
+        Simplification::Pairwise => pairwise(
+            ledger,
+            minor_units(&ledger.currency),
+            |exp| Some(exp.exchange_rate),
+            |_| true,
+        ),
+    }
+}
+
+/// Transfers computed separately for every currency expenses and payments were made in, without any
+/// conversion, so that debts can also be paid back in the original currency
+pub fn debts_by_currency(ledger: &Ledger) -> Result<BTreeMap<String, Vec<Transfer>>, Overflow> {
+    let currencies: BTreeSet<&str> = ledger
+        .expenses
+        .iter()
+        .map(|x| x.currency.as_str())
+        .chain(ledger.payments.iter().map(|x| x.currency.as_str()))
+        .collect();
+
+    currencies
+        .into_iter()
+        .map(|currency| {
+            let transfers = settle(
+                ledger,
+                minor_units(currency),
+                |exp| (exp.currency == currency).then_some(Decimal::ONE),
+                |payment| payment.currency == currency,
+            );
+            Ok((currency.to_owned(), transfers?))
+        })
+        .collect()
+}
+
+fn settle(
+    ledger: &Ledger,
+    decimals: u32,
+    rate: impl Fn(&expense::Model) -> Option<Decimal>,
+    counts: impl Fn(&payment::Model) -> bool,
+) -> Result<Vec<Transfer>, Overflow> {
+    match ledger.simplification {
+        Simplification::Greedy => Ok(transfers(&tally(ledger, decimals, rate, counts)?)),
+        Simplification::Minimal => minimal_transfers(&tally(ledger, decimals, rate, counts)?),
+        Simplification::Pairwise => pairwise(ledger, decimals, rate, counts),
+    }
+}
+
+/// Who an expense credits and who it debits, in whole minor units. Both sides add up to the same amount
+struct Flow {
+    /// Leftover minor units of the expense are handed out starting from this member
+    offset: usize,
+    credits: Vec<(i64, Decimal)>,
+    debits: Vec<(i64, Decimal)>,
+}
+
+/// Flows of the expenses `rate` returns a conversion rate for, rounded to `decimals` places
+fn flows(
+    ledger: &Ledger,
+    decimals: u32,
+    rate: impl Fn(&expense::Model) -> Option<Decimal>,
+) -> Result<Vec<Flow>, Overflow> {
+    let mut expense_participants: HashMap<i64, Vec<(i64, Option<Decimal>)>> = HashMap::new();
+    for participant in ledger.participants.iter() {
+        expense_participants
+            .entry(participant.expense_id)
+            .or_default()
+            .push((participant.user_id, participant.share));
+    }
+
+    let mut expense_payers: HashMap<i64, Vec<(i64, Decimal)>> = HashMap::new();
+    for payer in ledger.payers.iter() {
+        expense_payers
+            .entry(payer.expense_id)
+            .or_default()
+            .push((payer.user_id, payer.amount));
+    }
+
+    // Leftover minor units are handed out in the user id order
+    for sharing in expense_participants.values_mut() {
+        sharing.sort_by_key(|x| x.0);
+    }
+    for contributions in expense_payers.values_mut() {
+        contributions.sort_by_key(|x| x.0);
+    }
+
+    let mut flows = Vec::new();
+    for exp in ledger.expenses.iter() {
+        let Some(rate) = rate(exp) else {
+            continue;
+        };
+
+        // Whatever the expense was split into, the parts add up to this amount
+        let amount = exp
+            .amount
+            .checked_mul(rate)
+            .ok_or(Overflow)?
+            .round_dp(decimals);
+        let offset = exp.id.unsigned_abs() as usize;
+
+        let credits = match expense_payers.get(&exp.id) {
+            Some(contributions) => allocate(amount, contributions, decimals, offset)?,
+            None => vec![(exp.payer, amount)],
+        };
+
+        // Nobody to split with, so the payer covers the whole expense
+        let payer_only = [(exp.payer, None)];
+        let sharing: &[(i64, Option<Decimal>)] = match expense_participants.get(&exp.id) {
+            Some(sharing) => sharing,
+            None => &payer_only,
+        };
+
+        flows.push(Flow {
+            offset,
+            credits,
+            debits: split(amount, exp.split_mode, sharing, decimals, offset)?,
+        });
+    }
+
+    Ok(flows)
+}
+
+/// Balances of the members. Every payer of an expense is credited with their contribution.
+/// An expense is split among its participants according to its split mode, even among those who
+/// haven't spent anything yet. Participants are written down along with the expense, so members who
+/// join later aren't charged for it. Settle-up payments move the balance from the one who paid back to the one who received the money.
+/// Only expenses `rate` returns a conversion rate for and payments `counts` accepts are summed up.
+/// Every share is rounded to `decimals` places, so balances always add up to zero
+fn tally(
+    ledger: &Ledger,
+    decimals: u32,
+    rate: impl Fn(&expense::Model) -> Option<Decimal>,
+    counts: impl Fn(&payment::Model) -> bool,
+) -> Result<BTreeMap<i64, Decimal>, Overflow> {
+    let mut balances: BTreeMap<i64, Decimal> = ledger
+        .members
+        .iter()
+        .map(|member| (member.id, Decimal::ZERO))
+        .collect();
+    let mut add = |user_id: i64, amount: Decimal| -> Result<(), Overflow> {
+        let balance = balances.entry(user_id).or_default();
+        *balance = balance.checked_add(amount).ok_or(Overflow)?;
+        Ok(())
+    };
+
+    for flow in flows(ledger, decimals, rate)? {
+        for (user_id, credit) in flow.credits {
+            add(user_id, credit)?;
+        }
+        for (user_id, debit) in flow.debits {
+            add(user_id, -debit)?;
+        }
+    }
+
+    for payment in ledger.payments.iter().filter(|x| counts(x)) {
+        let amount = payment.amount.round_dp(decimals);
+        add(payment.from_user, amount)?;
+        add(payment.to_user, -amount)?;
+    }
+
+    Ok(balances)
+}
+
+/// Every participant owes the payers of an expense their share, split among payers by contribution.
+/// Debts of two members to each other are netted, but nobody pays anyone they don't owe
+fn pairwise(
+    ledger: &Ledger,
+    decimals: u32,
+    rate: impl Fn(&expense::Model) -> Option<Decimal>,
+    counts: impl Fn(&payment::Model) -> bool,
+) -> Result<Vec<Transfer>, Overflow> {
+    // Every pair of members is kept once, the smaller id first. A positive amount means that the first
+    // one owes the second, a negative one that it's the other way around
+    let mut owed: BTreeMap<(i64, i64), Decimal> = BTreeMap::new();
+    let mut add = |debtor: i64, creditor: i64, amount: Decimal| -> Result<(), Overflow> {
+        let (pair, amount) = match debtor.cmp(&creditor) {
+            Ordering::Less => ((debtor, creditor), amount),
+            Ordering::Greater => ((creditor, debtor), -amount),
+            Ordering::Equal => return Ok(()),
+        };
+        let owes = owed.entry(pair).or_default();
+        *owes = owes.checked_add(amount).ok_or(Overflow)?;
+        Ok(())
+    };
+
+    for flow in flows(ledger, decimals, rate)? {
+        // Every debt is split among what's left of the contributions, so that rounding never leaves
+        // a payer with more or less than they've paid
+        let mut left = flow.credits;
+        for (debtor, debt) in flow.debits {
+            let parts = allocate(debt, &left, decimals, flow.offset)?;
+            for ((creditor, part), (_, credit)) in parts.into_iter().zip(left.iter_mut()) {
+                *credit -= part;
+                add(debtor, creditor, part)?;
+            }
+        }
+    }
+
+    // Paying more than owed, or paying someone nothing was owed to, leaves the recipient owing back
+    for payment in ledger.payments.iter().filter(|x| counts(x)) {
+        add(
+            payment.to_user,
+            payment.from_user,
+            payment.amount.round_dp(decimals),
+        )?;
+    }
+
+    Ok(owed
+        .into_iter()
+        .filter_map(
+            |((first, second), amount)| match amount.cmp(&Decimal::ZERO) {
+                Ordering::Greater => Some(Transfer {
+                    from: first,
+                    to: second,
+                    amount,
+                }),
+                Ordering::Less => Some(Transfer {
+                    from: second,
+                    to: first,
+                    amount: -amount,
+                }),
+                Ordering::Equal => None,
+            },
+        )
+        .collect())
+}
+
+/// Members with non-zero balances, up to which the minimal number of transfers is searched exhaustively
+const MINIMAL_SEARCH_LIMIT: usize = 16;
+
+/// Members are split into as many groups with zero total balance as possible, each of them settled
+/// separately, which takes one transfer less per group than settling everybody at once.
+/// Larger groups fall back to the greedy matching. Totals of members are added up, which may overflow
+fn minimal_transfers(balances: &BTreeMap<i64, Decimal>) -> Result<Vec<Transfer>, Overflow> {
+    let owing: Vec<(i64, Decimal)> = balances
+        .iter()
+        .filter(|x| !x.1.is_zero())
+        .map(|(user_id, balance)| (*user_id, *balance))
+        .collect();
+
+    let count = owing.len();
+    if count > MINIMAL_SEARCH_LIMIT {
+        return Ok(transfers(balances));
+    }
+
+    // For every subset of members: its total balance, the largest number of zero-sum groups it can be
+    // split into one member at a time, and the member added last on the way to that number
+    let size = 1usize << count;
+    let mut sums = vec![Decimal::ZERO; size];
+    let mut groups = vec![0usize; size];
+    let mut last = vec![0usize; size];
+    for mask in 1..size {
+        let lowest = mask.trailing_zeros() as usize;
+        sums[mask] = sums[mask & (mask - 1)]
+            .checked_add(owing[lowest].1)
+            .ok_or(Overflow)?;
+
+        let mut best = None;
+        for member in (0..count).filter(|x| mask & (1 << x) != 0) {
+            let previous = groups[mask ^ (1 << member)];
+            if best.is_none_or(|(groups, _)| previous > groups) {
+                best = Some((previous, member));
+            }
+        }
+
+        let (previous, member) = best.unwrap_or_default();
+        groups[mask] = previous + usize::from(sums[mask].is_zero());
+        last[mask] = member;
+    }
+
+    let mut order = Vec::with_capacity(count);
+    let mut mask = size - 1;
+    while mask != 0 {
+        order.push(last[mask]);
+        mask ^= 1 << last[mask];
+    }
+
+    // Every time the members added so far add up to zero, they are settled among themselves
+    let mut result = Vec::new();
+    let mut group = BTreeMap::new();
+    let mut total = Decimal::ZERO;
+    for member in order.into_iter().rev() {
+        let (user_id, balance) = owing[member];
+        group.insert(user_id, balance);
+        total = total.checked_add(balance).ok_or(Overflow)?;
+
+        if total.is_zero() {
+            result.extend(transfers(&group));
+            group.clear();
+        }
+    }
+
+    Ok(result)
+}
+
+/// Greedily matches the largest debtors with the largest creditors until everybody is settled
+pub fn transfers(balances: &BTreeMap<i64, Decimal>) -> Vec<Transfer> {
+    let mut creditors: Vec<UserDebt> = Vec::new();
+    let mut debitors: Vec<UserDebt> = Vec::new();
+    let mut transactions: Vec<Transfer> = Vec::new();
+
+    // Separate users into creditors and debitors
+    for (user_id, balance) in balances {
+        match balance.cmp(&Decimal::ZERO) {
+            Ordering::Greater => {
+                creditors.push(UserDebt {
+                    user_id: *user_id,
+                    debt: balance.abs(),
+                });
+            }
+            Ordering::Less => {
+                debitors.push(UserDebt {
+                    user_id: *user_id,
+                    debt: balance.abs(),
+                });
+            }
+            _ => (),
+        }
+    }
+
+    // Sort creditors and debtors by debt amount. The sort is stable, so ties keep the user id order
+    creditors.sort_by_key(|x| std::cmp::Reverse(x.debt));
+    debitors.sort_by_key(|x| std::cmp::Reverse(x.debt));
+
+    // Match debtors and creditors
+    let mut debitor_index = 0;
+    let mut creditor_index = 0;
+
+    while debitor_index < debitors.len() && creditor_index < creditors.len() {
+        let debtor = &debitors[debitor_index];
+        let creditor = &creditors[creditor_index];
+
+        // Calculate the amount to transfer
+        let transfer_amount = debtor.debt.min(creditor.debt);
+
+        // Record the transaction
+        transactions.push(Transfer {
+            from: debtor.user_id,
+            to: creditor.user_id,
+            amount: transfer_amount,
+        });
+
+        // Adjust the debt values
+        debitors[debitor_index].debt -= transfer_amount;
+        creditors[creditor_index].debt -= transfer_amount;
+
+        // If a debtor's debt is fully matched, move to the next debitor
+        if debitors[debitor_index].debt == Decimal::ZERO {
+            debitor_index += 1;
+        }
+
+        // If a creditor's debt is fully matched, move to the next creditor
+        if creditors[creditor_index].debt == Decimal::ZERO {
+            creditor_index += 1;
+        }
+    }
+
+    transactions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{seq::SliceRandom, SeedableRng};
+
+    fn member(id: i64) -> user::Model {
+        user::Model {
+            id,
+            telegram_id: Some(id),
+            username: None,
+            display_name: format!("Member {id}"),
+        }
+    }
+
+    fn expense(id: i64, payer: i64, amount: Decimal) -> expense::Model {
+        expense::Model {
+            id,
+            payer,
+            created_by: payer,
+            group_id: 1,
+            amount,
+            currency: String::from("EUR"),
+            exchange_rate: Decimal::ONE,
+            note: String::new(),
+            split_mode: SplitMode::Equal,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    fn participant(expense_id: i64, user_id: i64) -> expense_participant::Model {
+        expense_participant::Model {
+            expense_id,
+            user_id,
+            share: None,
+        }
+    }
+
+    fn payment(id: i64, from_user: i64, to_user: i64, amount: Decimal) -> payment::Model {
+        payment::Model {
+            id,
+            group_id: 1,
+            from_user,
+            to_user,
+            amount,
+            currency: String::from("EUR"),
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    fn ledger(members: &[i64], simplification: Simplification) -> Ledger {
+        Ledger {
+            currency: String::from("EUR"),
+            simplification,
+            members: members.iter().copied().map(member).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Splits `total` into `count` positive whole parts
+    fn random_parts(rng: &mut impl rand::Rng, total: i64, count: usize) -> Vec<i64> {
+        let mut cuts: Vec<i64> = rand::seq::index::sample(rng, total as usize - 1, count - 1)
+            .into_iter()
+            .map(|x| x as i64 + 1)
+            .collect();
+        cuts.sort_unstable();
+        cuts.push(total);
+
+        let mut previous = 0;
+        cuts.into_iter()
+            .map(|cut| {
+                let part = cut - previous;
+                previous = cut;
+                part
+            })
+            .collect()
+    }
+
+    /// Group with random expenses in several currencies, split every possible way among random
+    /// participants and paid by one or several payers, along with random settle-up payments
+    fn random_ledger(rng: &mut impl rand::Rng, simplification: Simplification) -> Ledger {
+        let members: Vec<i64> = (1..=rng.gen_range(1..=8)).collect();
+        let mut ledger = ledger(&members, simplification);
+
+        for id in 1..=rng.gen_range(0..=12) {
+            let cents = rng.gen_range(100..=1_000_000);
+            let mut expense = expense(id, *members.choose(rng).unwrap(), Decimal::new(cents, 2));
+            if rng.gen_bool(0.3) {
+                expense.currency = String::from("USD");
+                expense.exchange_rate = Decimal::new(rng.gen_range(1..=300_000), 5);
+            }
+
+            let count = rng.gen_range(1..=members.len());
+            let sharing: Vec<i64> = members.choose_multiple(rng, count).copied().collect();
+            expense.split_mode = *[
+                SplitMode::Equal,
+                SplitMode::Exact,
+                SplitMode::Percent,
+                SplitMode::Shares,
+            ]
+            .choose(rng)
+            .unwrap();
+            let shares: Vec<Option<Decimal>> = match expense.split_mode {
+                SplitMode::Equal => vec![None; count],
+                SplitMode::Exact => random_parts(rng, cents, count)
+                    .into_iter()
+                    .map(|x| Some(Decimal::new(x, 2)))
+                    .collect(),
+                SplitMode::Percent => random_parts(rng, 100, count)
+                    .into_iter()
+                    .map(|x| Some(Decimal::from(x)))
+                    .collect(),
+                SplitMode::Shares => (0..count)
+                    .map(|_| Some(Decimal::from(rng.gen_range(1..=5))))
+                    .collect(),
+            };
+            for (user_id, share) in sharing.into_iter().zip(shares) {
+                ledger.participants.push(expense_participant::Model {
+                    expense_id: id,
+                    user_id,
+                    share,
+                });
+            }
+
+            if members.len() > 1 && rng.gen_bool(0.3) {
+                let count = rng.gen_range(2..=members.len().min(cents as usize));
+                let payers = members.choose_multiple(rng, count).copied();
+                for (user_id, part) in payers.zip(random_parts(rng, cents, count)) {
+                    ledger.payers.push(expense_payer::Model {
+                        expense_id: id,
+                        user_id,
+                        amount: Decimal::new(part, 2),
+                    });
+                }
+            }
+
+            ledger.expenses.push(expense);
+        }
+
+        if members.len() > 1 {
+            for id in 1..=rng.gen_range(0..=4) {
+                let pair: Vec<i64> = members.choose_multiple(rng, 2).copied().collect();
+                let amount = Decimal::new(rng.gen_range(1..=100_000), 2);
+                ledger.payments.push(payment(id, pair[0], pair[1], amount));
+            }
+        }
+
+        ledger
+    }
+
+    /// Balances once every transfer has been made
+    fn settled(
+        balances: &BTreeMap<i64, Decimal>,
+        transfers: &[Transfer],
+    ) -> BTreeMap<i64, Decimal> {
+        let mut balances = balances.clone();
+        for transfer in transfers {
+            *balances.entry(transfer.from).or_default() += transfer.amount;
+            *balances.entry(transfer.to).or_default() -= transfer.amount;
+        }
+        balances
+    }
+
+    #[test]
+    fn balances_sum_to_zero() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        for _ in 0..500 {
+            let ledger = random_ledger(&mut rng, Simplification::Greedy);
+            let total: Decimal = balances(&ledger).unwrap().values().sum();
+            assert_eq!(total, Decimal::ZERO, "{ledger:#?}");
+        }
+    }
+
+    #[test]
+    fn transfers_settle_everybody() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+        for _ in 0..500 {
+            let ledger = random_ledger(&mut rng, Simplification::Greedy);
+            let balances = balances(&ledger).unwrap();
+            let settled = settled(&balances, &transfers(&balances));
+            assert!(settled.values().all(|x| x.is_zero()), "{ledger:#?}");
+        }
+    }
+
+    #[test]
+    fn debts_settle_everybody_whatever_the_simplification() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        for simplification in [
+            Simplification::Greedy,
+            Simplification::Minimal,
+            Simplification::Pairwise,
+        ] {
+            for _ in 0..200 {
+                let ledger = random_ledger(&mut rng, simplification);
+                let balances = balances(&ledger).unwrap();
+                let settled = settled(&balances, &debts(&ledger).unwrap());
+                assert!(settled.values().all(|x| x.is_zero()), "{ledger:#?}");
+            }
+        }
+    }
+
+    #[test]
+    fn member_who_never_paid_owes_their_share() {
+        let mut ledger = ledger(&[1, 2, 3], Simplification::Greedy);
+        ledger.expenses.push(expense(1, 1, Decimal::from(30)));
+        ledger.expenses.push(expense(2, 2, Decimal::from(60)));
+        for expense_id in [1, 2] {
+            for user_id in [1, 2, 3] {
+                ledger.participants.push(participant(expense_id, user_id));
+            }
+        }
+
+        let balances = balances(&ledger).unwrap();
+        assert_eq!(balances[&3], Decimal::from(-30));
+
+        let expected = vec![Transfer {
+            from: 3,
+            to: 2,
+            amount: Decimal::from(30),
+        }];
+        assert_eq!(transfers(&balances), expected);
+        assert_eq!(debts(&ledger).unwrap(), expected);
+    }
+
+    #[test]
+    fn minimal_simplification_takes_fewer_transfers() {
+        let balances: BTreeMap<i64, Decimal> = [(1, -6), (2, -5), (3, 1), (4, 5), (5, 5)]
+            .into_iter()
+            .map(|(user_id, balance)| (user_id, Decimal::from(balance)))
+            .collect();
+
+        let greedy = transfers(&balances);
+        let minimal = minimal_transfers(&balances).unwrap();
+        assert_eq!(greedy.len(), 4);
+        assert_eq!(minimal.len(), 3);
+        assert!(settled(&balances, &minimal).values().all(|x| x.is_zero()));
+    }
+
+    #[test]
+    fn amounts_too_large_to_add_up_are_an_error() {
+        for simplification in [
+            Simplification::Greedy,
+            Simplification::Minimal,
+            Simplification::Pairwise,
+        ] {
+            let mut ledger = ledger(&[1, 2], simplification);
+            ledger.expenses.push(expense(1, 1, Decimal::MAX));
+            ledger.expenses[0].exchange_rate = Decimal::TWO;
+            ledger.participants = vec![participant(1, 1), participant(1, 2)];
+            assert_eq!(debts(&ledger), Err(Overflow));
+
+            ledger.expenses[0].exchange_rate = Decimal::ONE;
+            ledger.expenses.push(expense(2, 1, Decimal::MAX));
+            ledger.participants.push(participant(2, 2));
+            assert_eq!(debts(&ledger), Err(Overflow));
+        }
+
+        // Balances that fit on their own may still be too large to add up for the minimal search
+        let balances = BTreeMap::from([
+            (1, Decimal::MAX),
+            (2, Decimal::MAX),
+            (3, Decimal::MIN),
+            (4, Decimal::MIN),
+        ]);
+        assert_eq!(minimal_transfers(&balances), Err(Overflow));
+    }
+
+    #[test]
+    fn currencies_are_known_by_their_code() {
+        assert!(CURRENCIES.windows(2).all(|x| x[0] < x[1]));
+        assert!(is_currency("EUR"));
+        assert!(is_currency("JPY"));
+        assert!(!is_currency("BBQ"));
+        assert!(!is_currency("eur"));
+    }
+
+    #[test]
+    fn amounts_and_rates_are_bounded() {
+        assert_eq!(validate_amount(MAX_AMOUNT), Ok(()));
+        assert_eq!(
+            validate_amount(MAX_AMOUNT + Decimal::ONE),
+            Err(SplitError::AmountTooLarge)
+        );
+        assert_eq!(
+            validate_amount(Decimal::ZERO),
+            Err(SplitError::NonPositiveAmount)
+        );
+        assert_eq!(validate_minor_units(Decimal::new(10050, 3), "EUR"), Ok(()));
+        assert_eq!(
+            validate_minor_units(Decimal::new(10005, 3), "EUR"),
+            Err(SplitError::TooManyDecimals {
+                currency: "EUR".to_owned(),
+                decimals: 2
+            })
+        );
+        assert!(validate_minor_units(Decimal::new(105, 1), "JPY").is_err());
+        assert_eq!(validate_rate(MAX_RATE), Ok(()));
+        assert_eq!(
+            validate_rate(MAX_RATE + Decimal::ONE),
+            Err(SplitError::RateOutOfRange)
+        );
+        assert_eq!(validate_rate(MIN_RATE), Ok(()));
+        assert_eq!(
+            validate_rate(MIN_RATE / Decimal::TWO),
+            Err(SplitError::RateOutOfRange)
+        );
+        assert_eq!(
+            validate_rate(Decimal::ZERO),
+            Err(SplitError::RateOutOfRange)
+        );
+    }
+
+    #[test]
+    fn fingerprint_changes_with_the_transfers() {
+        let mut ledger = ledger(&[1, 2], Simplification::Greedy);
+        ledger.expenses.push(expense(1, 1, Decimal::from(10)));
+        ledger.participants = vec![participant(1, 1), participant(1, 2)];
+        let shown = fingerprint(&debts(&ledger).unwrap());
+        assert_eq!(fingerprint(&debts(&ledger).unwrap()), shown);
+
+        ledger.payments.push(payment(1, 2, 1, Decimal::from(2)));
+        assert_ne!(fingerprint(&debts(&ledger).unwrap()), shown);
+
+        // The same transfers give the same fingerprint in every build, however the amount is scaled
+        let transfer = |amount| Transfer {
+            from: 1,
+            to: 2,
+            amount,
+        };
+        assert_eq!(
+            fingerprint(&[transfer(Decimal::new(125, 1))]),
+            0x2a28_7748_ca88_e489
+        );
+        assert_eq!(
+            fingerprint(&[transfer(Decimal::new(1250, 2))]),
+            0x2a28_7748_ca88_e489
+        );
+    }
+
+    #[test]
+    fn pairwise_overpayment_is_owed_back() {
+        let mut ledger = ledger(&[1, 2], Simplification::Pairwise);
+        ledger.expenses.push(expense(1, 1, Decimal::from(10)));
+        ledger.participants = vec![participant(1, 1), participant(1, 2)];
+        ledger.payments.push(payment(1, 2, 1, Decimal::from(8)));
+
+        assert_eq!(
+            debts(&ledger).unwrap(),
+            vec![Transfer {
+                from: 1,
+                to: 2,
+                amount: Decimal::from(3),
+            }]
+        );
+    }
+
+    #[test]
+    fn pairwise_payment_without_shared_expenses_is_owed_back() {
+        let mut ledger = ledger(&[1, 2, 3], Simplification::Pairwise);
+        ledger.expenses.push(expense(1, 1, Decimal::from(10)));
+        ledger.participants = vec![participant(1, 1), participant(1, 2)];
+        ledger.payments.push(payment(1, 3, 1, Decimal::from(4)));
+
+        assert_eq!(
+            debts(&ledger).unwrap(),
+            vec![
+                Transfer {
+                    from: 2,
+                    to: 1,
+                    amount: Decimal::from(5),
+                },
+                Transfer {
+                    from: 1,
+                    to: 3,
+                    amount: Decimal::from(4),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use crate::db::{self, Database};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{marker::PhantomData, sync::Arc};
+use teloxide::types::{ChatId, UserId};
+use tracing::warn;
+
+#[derive(Debug)]
+pub enum Error {
+    Database(db::Error),
+    Serialization(serde_json::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Database(ref err) => write!(f, "{}", err),
+            Self::Serialization(ref err) => write!(f, "Dialogue serialization error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<db::Error> for Error {
+    fn from(err: db::Error) -> Self {
+        Self::Database(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Serialization(err)
+    }
+}
+
+/// Keeps dialogues as JSON in the bot database, so that a restart doesn't interrupt them.
+/// A dialogue nobody has touched for longer than `timeout` is forgotten and starts over
+pub struct DbStorage<D> {
+    db: &'static Database,
+    timeout: Option<chrono::Duration>,
+    state: PhantomData<fn() -> D>,
+}
+
+impl<D> DbStorage<D> {
+    /// Zero `timeout` keeps dialogues forever
+    pub fn new(db: &'static Database, timeout: chrono::Duration) -> Arc<Self> {
+        Arc::new(Self {
+            db,
+            timeout: Some(timeout).filter(|x| *x > chrono::Duration::zero()),
+            state: PhantomData,
+        })
+    }
+
+    /// Removes every dialogue that has already expired
+    pub async fn remove_stale(&self) -> Result<u64, Error> {
+        match self.timeout {
+            Some(timeout) => Ok(self
+                .db
+                .remove_stale_dialogues(chrono::Utc::now() - timeout)
+                .await?),
+            None => Ok(0),
+        }
+    }
+}
+
+impl<D> DbStorage<D>
+where
+    D: Serialize + DeserializeOwned + Send + 'static,
+{
+    async fn remove_dialogue(&self, chat_id: ChatId, user_id: UserId) -> Result<(), Error> {
+        Ok(self.db.remove_dialogue(chat_id.0, user_id.0 as i64).await?)
+    }
+
+    async fn update_dialogue(
+        &self,
+        chat_id: ChatId,
+        user_id: UserId,
+        dialogue: D,
+    ) -> Result<(), Error> {
+        let state = serde_json::to_string(&dialogue)?;
+        Ok(self
+            .db
+            .upsert_dialogue(chat_id.0, user_id.0 as i64, state)
+            .await?)
+    }
+
+    async fn get_dialogue(&self, chat_id: ChatId, user_id: UserId) -> Result<Option<D>, Error> {
+        let Some(dialogue) = self.db.get_dialogue(chat_id.0, user_id.0 as i64).await? else {
+            return Ok(None);
+        };
+
+        if self
+            .timeout
+            .is_some_and(|timeout| dialogue.updated_at + timeout < chrono::Utc::now())
+        {
+            self.remove_dialogue(chat_id, user_id).await?;
+            return Ok(None);
+        }
+
+        // A state saved by an older version of the bot may not fit anymore, so the dialogue starts over
+        match serde_json::from_str(&dialogue.state) {
+            Ok(state) => Ok(Some(state)),
+            Err(err) => {
+                warn!("Dropping unreadable dialogue of {user_id} in chat {chat_id}. Err: {err}");
+                self.remove_dialogue(chat_id, user_id).await?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Dialogue of a user in a chat. Unlike the dialogues of teloxide, which belong to a whole chat, every
+/// member of a group chat has their own one, so that nobody can answer a question asked to somebody else
+pub struct Dialogue<D> {
+    storage: Arc<DbStorage<D>>,
+    chat_id: ChatId,
+    user_id: UserId,
+}
+
+impl<D> Clone for Dialogue<D> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: Arc::clone(&self.storage),
+            chat_id: self.chat_id,
+            user_id: self.user_id,
+        }
+    }
+}
+
+impl<D> Dialogue<D>
+where
+    D: Serialize + DeserializeOwned + Default + Send + 'static,
+{
+    pub fn new(storage: Arc<DbStorage<D>>, chat_id: ChatId, user_id: UserId) -> Self {
+        Self {
+            storage,
+            chat_id,
+            user_id,
+        }
+    }
+
+    pub fn chat_id(&self) -> ChatId {
+        self.chat_id
+    }
+
+    pub async fn get_or_default(&self) -> Result<D, Error> {
+        Ok(self
+            .storage
+            .get_dialogue(self.chat_id, self.user_id)
+            .await?
+            .unwrap_or_default())
+    }
+
+    pub async fn update(&self, state: impl Into<D>) -> Result<(), Error> {
+        self.storage
+            .update_dialogue(self.chat_id, self.user_id, state.into())
+            .await
+    }
+
+    pub async fn exit(&self) -> Result<(), Error> {
+        self.storage
+            .remove_dialogue(self.chat_id, self.user_id)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    enum State {
+        #[default]
+        Start,
+        Asked {
+            group_id: i64,
+        },
+    }
+
+    async fn storage(timeout: chrono::Duration) -> Arc<DbStorage<State>> {
+        let db = Database::in_memory().await.unwrap();
+        db.apply_migrations().await.unwrap();
+        DbStorage::new(Box::leak(Box::new(db)), timeout)
+    }
+
+    #[tokio::test]
+    async fn dialogues_are_kept_per_user_until_they_exit() {
+        let storage = storage(chrono::Duration::zero()).await;
+        let alice = Dialogue::new(Arc::clone(&storage), ChatId(-100), UserId(1));
+        let bob = Dialogue::new(Arc::clone(&storage), ChatId(-100), UserId(2));
+
+        alice.update(State::Asked { group_id: 7 }).await.unwrap();
+        assert_eq!(
+            alice.get_or_default().await.unwrap(),
+            State::Asked { group_id: 7 }
+        );
+        assert_eq!(bob.get_or_default().await.unwrap(), State::Start);
+
+        alice.exit().await.unwrap();
+        assert_eq!(alice.get_or_default().await.unwrap(), State::Start);
+    }
+
+    #[tokio::test]
+    async fn dialogues_expire_after_the_timeout() {
+        let storage = storage(chrono::Duration::milliseconds(20)).await;
+        let alice = Dialogue::new(Arc::clone(&storage), ChatId(1), UserId(1));
+        let bob = Dialogue::new(Arc::clone(&storage), ChatId(2), UserId(2));
+
+        alice.update(State::Asked { group_id: 7 }).await.unwrap();
+        bob.update(State::Asked { group_id: 8 }).await.unwrap();
+        assert_eq!(
+            alice.get_or_default().await.unwrap(),
+            State::Asked { group_id: 7 }
+        );
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(alice.get_or_default().await.unwrap(), State::Start);
+        assert!(storage.db.get_dialogue(1, 1).await.unwrap().is_none());
+
+        // The ones nobody comes back to are swept up
+        assert_eq!(storage.remove_stale().await.unwrap(), 1);
+        assert!(storage.db.get_dialogue(2, 2).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn unreadable_dialogues_start_over() {
+        let storage = storage(chrono::Duration::zero()).await;
+        let alice = Dialogue::new(Arc::clone(&storage), ChatId(1), UserId(1));
+
+        storage
+            .db
+            .upsert_dialogue(1, 1, r#"{"Removed":{}}"#.to_owned())
+            .await
+            .unwrap();
+        assert_eq!(alice.get_or_default().await.unwrap(), State::Start);
+        assert!(storage.db.get_dialogue(1, 1).await.unwrap().is_none());
+    }
+}